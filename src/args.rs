@@ -1,4 +1,6 @@
-use clap::Parser;
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand, ValueEnum};
 
 /// OMCP - A Model Context Protocol client for interacting with various MCP servers
 #[derive(Parser, Debug, Clone)]
@@ -56,10 +58,34 @@ pub struct Args {
     #[arg(
         short = 'o',
         long = "ollama-host",
-        help = "Specify the Ollama host URL (e.g., http://localhost:11434)"
+        help = "Specify the Ollama host URL (e.g., http://localhost:11434 or https://example.com/ollama)"
     )]
     pub ollama_host: Option<String>,
 
+    /// Toolset to apply at startup
+    #[arg(
+        short = 't',
+        long = "toolset",
+        help = "Name of a toolset from the settings to enable at startup"
+    )]
+    pub toolset: Option<String>,
+
+    /// Path to a .json tool permission policy file
+    #[arg(
+        short = 'P',
+        long = "policy",
+        help = "Path to a JSON tool permission policy file overriding the default one"
+    )]
+    pub policy: Option<String>,
+
+    /// Approve tool calls the policy asks about in batch mode
+    #[arg(
+        short = 'y',
+        long = "yes",
+        help = "Approve tool calls that would ask for confirmation when running a prompt with --prompt"
+    )]
+    pub yes: bool,
+
     /// Start instantly with the provided prompt and return the result
     #[arg(
         short = 'p',
@@ -67,6 +93,88 @@ pub struct Args {
         help = "Execute a prompt immediately and return the result without entering interactive mode"
     )]
     pub prompt: Option<String>,
+
+    /// Output format of the answer in batch mode
+    #[arg(
+        long = "format",
+        value_enum,
+        requires = "prompt",
+        help = "Output format of the answer when running a prompt with --prompt"
+    )]
+    pub format: Option<OutputFormat>,
+
+    /// JSON schema the answer must conform to in batch mode
+    #[arg(
+        long = "schema",
+        requires = "prompt",
+        help = "Path to a JSON schema file the answer must conform to (implies --format json)"
+    )]
+    pub schema: Option<PathBuf>,
+
+    /// Number of retries when the answer doesn't conform to the format
+    #[arg(
+        long = "format-retries",
+        default_value_t = 2,
+        help = "Number of times the model is asked again when its answer doesn't conform to --format or --schema"
+    )]
+    pub format_retries: usize,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Show the tool calls recorded in the audit log
+    Audit(AuditArgs),
+}
+
+/// Filters for the audit log view
+#[derive(clap::Args, Debug, Clone)]
+pub struct AuditArgs {
+    /// Only show calls to tools matching this glob pattern
+    #[arg(long, help = "Only show calls to tools matching this glob pattern")]
+    pub tool: Option<String>,
+
+    /// Only show calls to servers matching this glob pattern
+    #[arg(long, help = "Only show calls to servers matching this glob pattern")]
+    pub server: Option<String>,
+
+    /// Only show calls from this session
+    #[arg(long, help = "Only show calls from the session with this id")]
+    pub session: Option<String>,
+
+    /// Only show calls made at or after this time
+    #[arg(
+        long,
+        help = "Only show calls made at or after this UTC time (e.g., 2025-01-31 or 2025-01-31T12:00)"
+    )]
+    pub since: Option<String>,
+
+    /// Only show calls that failed
+    #[arg(long, help = "Only show tool calls that returned an error")]
+    pub errors: bool,
+
+    /// Only show calls that were not allowed
+    #[arg(
+        long,
+        help = "Only show tool calls that were denied or had invalid arguments"
+    )]
+    pub denied: bool,
+
+    /// Maximum number of records to show
+    #[arg(short = 'n', long, help = "Only show the most recent N records")]
+    pub limit: Option<usize>,
+
+    /// Print records as JSON lines
+    #[arg(long, help = "Print the matching records as JSON lines")]
+    pub json: bool,
 }
 
 impl Args {
@@ -0,0 +1,254 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    AppResult,
+    args::AuditArgs,
+    tools::glob_match,
+    ui::utils::{AnsiColor, colorize_text},
+};
+
+/// Size after which the audit log is rotated
+const MAX_AUDIT_LOG_SIZE: u64 = 10 * 1024 * 1024;
+/// Number of rotated audit logs kept besides the current one
+const MAX_AUDIT_LOG_FILES: usize = 5;
+/// Maximum number of characters of a tool result stored in the audit log
+const MAX_AUDIT_RESULT_LENGTH: usize = 1000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub timestamp: String,
+    pub session_id: String,
+    pub server: Option<String>,
+    pub tool: String,
+    pub arguments: Value,
+    /// `allowed`, `denied` or `invalid`
+    pub decision: String,
+    /// What made the decision: `user`, `policy`, `settings`, `session`, `always_allowed`, `batch_mode` or `validation`
+    pub decided_by: String,
+    pub duration_ms: Option<u64>,
+    pub is_error: bool,
+    /// Whether the result came from the tool result cache instead of the server
+    #[serde(default)]
+    pub cached: bool,
+    pub result: String,
+}
+
+impl AuditRecord {
+    pub fn new(session_id: &str, tool: &str, arguments: Value) -> Self {
+        AuditRecord {
+            timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            session_id: session_id.to_string(),
+            server: None,
+            tool: tool.to_string(),
+            arguments,
+            decision: "invalid".to_string(),
+            decided_by: "validation".to_string(),
+            duration_ms: None,
+            is_error: false,
+            cached: false,
+            result: String::new(),
+        }
+    }
+
+    pub fn set_decision(&mut self, allowed: bool, decided_by: &str) {
+        self.decision = if allowed { "allowed" } else { "denied" }.to_string();
+        self.decided_by = decided_by.to_string();
+    }
+
+    pub fn set_result(&mut self, result: &str) {
+        self.result = result.chars().take(MAX_AUDIT_RESULT_LENGTH).collect();
+    }
+}
+
+/// Append-only JSONL log of the tool calls made during a session, rotated by size
+#[derive(Debug)]
+pub struct AuditLog {
+    pub file_path: PathBuf,
+    pub session_id: String,
+    write_lock: Mutex<()>,
+}
+
+impl AuditLog {
+    pub fn new(file_path: PathBuf) -> Self {
+        AuditLog {
+            file_path,
+            session_id: format!(
+                "{}-{}",
+                Utc::now().format("%Y%m%d%H%M%S"),
+                std::process::id()
+            ),
+            write_lock: Mutex::new(()),
+        }
+    }
+
+    pub fn record(&self, record: &AuditRecord) -> AppResult<()> {
+        let _guard = self.write_lock.lock().unwrap();
+
+        if fs::metadata(&self.file_path).is_ok_and(|m| m.len() >= MAX_AUDIT_LOG_SIZE) {
+            self.rotate()?;
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.file_path)?;
+        writeln!(file, "{}", serde_json::to_string(record)?)?;
+        Ok(())
+    }
+
+    /// Shifts `audit.jsonl` to `audit.1.jsonl`, `audit.1.jsonl` to `audit.2.jsonl`, etc., dropping the oldest one
+    fn rotate(&self) -> AppResult<()> {
+        for index in (1..MAX_AUDIT_LOG_FILES).rev() {
+            let from = rotated_path(&self.file_path, index);
+            if from.exists() {
+                fs::rename(&from, rotated_path(&self.file_path, index + 1))?;
+            }
+        }
+        fs::rename(&self.file_path, rotated_path(&self.file_path, 1))?;
+        Ok(())
+    }
+}
+
+fn rotated_path(file_path: &Path, index: usize) -> PathBuf {
+    let stem = file_path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    file_path.with_file_name(format!("{}.{}.jsonl", stem, index))
+}
+
+/// Reads the audit log and its rotated files, oldest records first
+pub fn read_audit_log(file_path: &Path) -> Vec<AuditRecord> {
+    let mut files: Vec<PathBuf> = (1..=MAX_AUDIT_LOG_FILES)
+        .rev()
+        .map(|index| rotated_path(file_path, index))
+        .collect();
+    files.push(file_path.to_path_buf());
+
+    files
+        .iter()
+        .filter_map(|file| fs::read_to_string(file).ok())
+        .flat_map(|content| {
+            content
+                .lines()
+                .filter_map(|line| serde_json::from_str::<AuditRecord>(line).ok())
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Parses the `--since` filter: a date, a date and time, or an RFC 3339 timestamp, in UTC by default
+fn parse_since(value: &str) -> AppResult<DateTime<Utc>> {
+    let value = value.trim();
+    if let Ok(timestamp) = DateTime::parse_from_rfc3339(value) {
+        return Ok(timestamp.with_timezone(&Utc));
+    }
+
+    for format in [
+        "%Y-%m-%dT%H:%M:%S",
+        "%Y-%m-%dT%H:%M",
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%d %H:%M",
+    ] {
+        if let Ok(timestamp) = NaiveDateTime::parse_from_str(value, format) {
+            return Ok(timestamp.and_utc());
+        }
+    }
+
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map(|date| date.and_time(NaiveTime::MIN).and_utc())
+        .map_err(|_| {
+            format!(
+                "Invalid --since value '{}', expected a date such as 2025-01-31 or a date and time such as 2025-01-31T12:00",
+                value
+            )
+            .into()
+        })
+}
+
+/// Prints the audit log records matching the `omcp audit` filters
+pub fn render_audit_log(file_path: &Path, args: &AuditArgs) -> AppResult<()> {
+    let since = args.since.as_deref().map(parse_since).transpose()?;
+
+    let records: Vec<AuditRecord> = read_audit_log(file_path)
+        .into_iter()
+        .filter(|r| {
+            args.tool
+                .as_ref()
+                .is_none_or(|tool| glob_match(tool, &r.tool))
+        })
+        .filter(|r| {
+            args.server.as_ref().is_none_or(|server| {
+                r.server
+                    .as_ref()
+                    .is_some_and(|record_server| glob_match(server, record_server))
+            })
+        })
+        .filter(|r| args.session.as_ref().is_none_or(|s| r.session_id == *s))
+        .filter(|r| {
+            since.is_none_or(|since| {
+                DateTime::parse_from_rfc3339(&r.timestamp).is_ok_and(|timestamp| timestamp >= since)
+            })
+        })
+        .filter(|r| !args.errors || r.is_error)
+        .filter(|r| !args.denied || r.decision != "allowed")
+        .collect();
+
+    let skipped = records
+        .len()
+        .saturating_sub(args.limit.unwrap_or(records.len()));
+
+    for record in records.iter().skip(skipped) {
+        if args.json {
+            println!("{}", serde_json::to_string(record)?);
+            continue;
+        }
+
+        let decision_color = match record.decision.as_str() {
+            "allowed" if record.is_error => AnsiColor::BrightYellow,
+            "allowed" => AnsiColor::BrightGreen,
+            _ => AnsiColor::BrightRed,
+        };
+
+        println!(
+            "{} {} {}/{} {} {}{}{}",
+            colorize_text(&record.timestamp, AnsiColor::BrightBlack),
+            colorize_text(&record.session_id, AnsiColor::BrightBlack),
+            record.server.as_deref().unwrap_or("?"),
+            colorize_text(&record.tool, AnsiColor::BrightCyan),
+            colorize_text(
+                &format!("{} ({})", record.decision, record.decided_by),
+                decision_color
+            ),
+            record
+                .duration_ms
+                .map(|d| format!("{}ms", d))
+                .unwrap_or_default(),
+            if record.cached {
+                colorize_text(" cached", AnsiColor::BrightBlack)
+            } else {
+                String::new()
+            },
+            if record.is_error {
+                colorize_text(" error", AnsiColor::BrightRed)
+            } else {
+                String::new()
+            }
+        );
+        println!("  Arguments: {}", record.arguments);
+        if !record.result.is_empty() {
+            println!("  Result: {}", record.result.replace('\n', " "));
+        }
+    }
+
+    Ok(())
+}
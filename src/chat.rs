@@ -1,41 +1,98 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 
+use futures::future::join_all;
 use ollama_rs::{
     Ollama,
     generation::{
         chat::{ChatMessage, ChatMessageResponse, MessageRole, request::ChatMessageRequest},
-        tools::ToolInfo,
+        tools::{ToolCall, ToolCallFunction, ToolInfo},
     },
-    models::ModelOptions,
 };
+use rmcp::model::CallToolResult;
+use serde_json::{Map, Value};
 use tokio::{
     io::{AsyncWriteExt, stdout},
-    sync::mpsc::{self, Receiver},
+    sync::{
+        Semaphore,
+        mpsc::{self, Receiver},
+    },
 };
 use tokio_stream::StreamExt;
 
 use crate::{
-    AppResult,
-    settings::SettingsManager,
-    tools::{ToolManager, tool::ToToolInfo},
+    AppResult, ConfigFile,
+    audit::{AuditLog, AuditRecord},
+    context::{
+        OLLAMA_DEFAULT_NUM_CTX, estimate_tokens, split_turns, summarize_messages, turns_to_reduce,
+    },
+    get_config_path,
+    model::ModelInfoCache,
+    ollama::{create_ollama_client, send_chat_stream},
+    settings::{ContextStrategy, SettingsManager},
+    tools::{
+        ToolManager,
+        fallback::{parse_tool_calls, render_tool_results, render_tools_prompt},
+        policy::PolicyAction,
+        retrieval::ToolRetriever,
+        schema::{
+            canonical_json, format_validation_errors, render_validation_errors, validate_arguments,
+        },
+        tool::MCPTool,
+    },
     ui::{
         input::{self, MenuChoice},
-        tools::{render_tool_call_request, render_tool_call_result},
+        tools::{
+            format_tool_result_content, render_tool_call_error, render_tool_call_request,
+            render_tool_call_result,
+        },
+        utils::{AnsiColor, colorize_text},
     },
 };
 
 #[derive(Debug, Clone)]
 pub struct ChatHistory {
     pub messages: Arc<Mutex<Vec<ChatMessage>>>,
+    /// Tokens used by the last request and its answer, with the number of messages they cover
+    token_count: Arc<Mutex<Option<(usize, u64)>>>,
 }
 
 impl ChatHistory {
     pub fn new() -> Self {
         ChatHistory {
             messages: Arc::new(Mutex::new(Vec::new())),
+            token_count: Arc::new(Mutex::new(None)),
         }
     }
 
+    fn record_token_count(&self, tokens: u64) {
+        let covered = self.messages.lock().unwrap().len();
+        *self.token_count.lock().unwrap() = Some((covered, tokens));
+    }
+
+    /// Must be called when messages are removed or replaced, as the token count no longer matches them
+    fn reset_token_count(&self) {
+        *self.token_count.lock().unwrap() = None;
+    }
+
+    /// Tokens used by the history, from the last token count reported by Ollama plus an estimate of
+    /// the messages added since
+    pub fn estimated_tokens(&self) -> u64 {
+        let messages = self.messages.lock().unwrap();
+        let (covered, counted) = match *self.token_count.lock().unwrap() {
+            Some((covered, counted)) if covered <= messages.len() => (covered, counted),
+            _ => (0, 0),
+        };
+
+        // prompt_eval_count can be lower than the prompt size when Ollama reuses its cache
+        let covered_estimate: u64 = messages[..covered].iter().map(estimate_tokens).sum();
+        let added_estimate: u64 = messages[covered..].iter().map(estimate_tokens).sum();
+        counted.max(covered_estimate) + added_estimate
+    }
+
     pub fn get_history(&self) -> Arc<Mutex<Vec<ChatMessage>>> {
         self.messages.clone()
     }
@@ -46,6 +103,7 @@ impl ChatHistory {
             .lock()
             .map_err(|e| format!("Failed to lock history for clearing: {}", e))?;
         history_guard.clear();
+        self.reset_token_count();
         Ok(())
     }
 }
@@ -53,7 +111,17 @@ impl ChatHistory {
 #[derive(Clone, Debug)]
 pub struct OllamaChat {
     pub ollama: Ollama,
+    pub http_client: reqwest::Client,
     history: ChatHistory,
+    last_tool_result: Arc<Mutex<Option<String>>>,
+    session_allowed_tools: Arc<Mutex<HashSet<String>>>,
+    batch_mode: bool,
+    assume_yes: bool,
+    audit_log: Arc<AuditLog>,
+    model_cache: ModelInfoCache,
+    tool_retriever: Arc<tokio::sync::Mutex<ToolRetriever>>,
+    /// Ollama `format` parameter (`"json"` or a JSON schema) of the final answers
+    output_format: Option<Value>,
     tool_manager: Arc<tokio::sync::Mutex<ToolManager>>,
     settings_manager: Arc<Mutex<SettingsManager>>,
 }
@@ -62,25 +130,33 @@ impl OllamaChat {
     pub fn new(
         tool_manager: Arc<tokio::sync::Mutex<ToolManager>>,
         settings_manager: Arc<Mutex<SettingsManager>>,
-        ollama_host: Option<String>,
-    ) -> Self {
-        let ollama = if let Some(host) = ollama_host {
-            let (host, port) = host
-                .split_once(':')
-                .map(|(h, p)| (h.to_string(), p.parse::<u16>().unwrap_or(11434)))
-                .unwrap_or((host.clone(), 11434));
-
-            Ollama::new(host, port)
-        } else {
-            Ollama::default()
-        };
+    ) -> AppResult<Self> {
+        let (ollama, http_client) = create_ollama_client(&settings_manager.lock().unwrap())?;
 
-        OllamaChat {
+        Ok(OllamaChat {
             ollama,
+            http_client,
             history: ChatHistory::new(),
+            last_tool_result: Arc::new(Mutex::new(None)),
+            session_allowed_tools: Arc::new(Mutex::new(HashSet::new())),
+            batch_mode: false,
+            assume_yes: false,
+            audit_log: Arc::new(AuditLog::new(get_config_path(ConfigFile::AuditLog))),
+            model_cache: ModelInfoCache::default(),
+            tool_retriever: Arc::new(tokio::sync::Mutex::new(ToolRetriever::load(
+                get_config_path(ConfigFile::ToolEmbeddings),
+            ))),
+            output_format: None,
             tool_manager,
             settings_manager,
-        }
+        })
+    }
+
+    /// Rebuilds the Ollama client after the connection settings changed
+    pub fn reconnect(&mut self) -> AppResult<()> {
+        (self.ollama, self.http_client) =
+            create_ollama_client(&self.settings_manager.lock().unwrap())?;
+        Ok(())
     }
 
     pub async fn chat(
@@ -90,198 +166,272 @@ impl OllamaChat {
         let model_name = self.settings_manager.lock().unwrap().model_name.clone();
         let mut messages = messages;
 
-        let tools_capability = self
-            .ollama
-            .show_model_info(model_name.clone())
-            .await
-            .unwrap()
-            .capabilities
-            .contains(&"tools".to_string());
+        let model_details = self
+            .model_cache
+            .get(&self.ollama, &self.http_client, &model_name)
+            .await?;
+        let tools_capability = model_details.has_capability("tools");
+        let thinking_capability = model_details.has_capability("thinking");
 
-        let thinking_capability = self
-            .ollama
-            .show_model_info(model_name.clone())
-            .await
-            .unwrap()
-            .capabilities
-            .contains(&"thinking".to_string());
+        let model_settings = self.settings_manager.lock().unwrap().with_model_overrides();
+        let model_options = model_settings.model_options();
+        let mut system_prompt = model_settings.model_system_prompt.clone();
+        let keep_thinking = model_settings.keep_thinking_history;
+        let extra_fields = request_extra_fields(&model_settings, thinking_capability);
+        let mut format_extra_fields = extra_fields.clone();
+        if let Some(format) = &self.output_format {
+            format_extra_fields.insert("format".to_string(), format.clone());
+        }
 
-        let mut model_options = ModelOptions::default();
+        if !keep_thinking {
+            for message in self.history.messages.lock().unwrap().iter_mut() {
+                message.thinking = None;
+            }
+        }
 
-        {
-            let settings = self.settings_manager.lock().unwrap();
-            model_options = model_options
-                .seed(settings.model_seed)
-                .temperature(settings.model_temperature);
+        self.manage_context(
+            &model_settings,
+            model_details.context_length,
+            &messages,
+            ChatMessageRequest::new(model_name.clone(), vec![]).options(model_options.clone()),
+            &extra_fields,
+        )
+        .await;
 
-            if !settings.model_system_prompt.is_empty() {
-                if messages.is_empty() || messages[0].role != MessageRole::System {
-                    messages.insert(0, ChatMessage::system(settings.model_system_prompt.clone()));
-                } else {
-                    messages[0].content = settings.model_system_prompt.clone();
-                }
+        if !messages.is_empty() && messages[0].role == MessageRole::System {
+            system_prompt = messages.remove(0).content;
+        }
+
+        let (tools, prompt_tools) = self.model_tools(&messages).await?;
+        let has_tools = prompt_tools || (tools_capability && !tools.is_empty());
+
+        if prompt_tools {
+            if !system_prompt.is_empty() {
+                system_prompt.push_str("\n\n");
             }
+            system_prompt.push_str(&render_tools_prompt(&tools));
         }
+        self.set_system_prompt(system_prompt);
 
         let mut request =
             ChatMessageRequest::new(model_name.clone(), messages).options(model_options.clone());
-        let tools: Vec<ToolInfo> = self
-            .tool_manager
-            .lock()
-            .await
-            .get_enabled_tools()
-            .iter()
-            .map(|t| t.tool_info.to_tool_info())
-            .collect();
 
         if tools_capability {
             request = request.tools(tools.clone());
         }
 
-        if thinking_capability {
-            request = request.think(true);
-        }
-
-        let mut stream = match self
-            .ollama
-            .send_chat_messages_with_history_stream(self.history.get_history(), request)
-            .await
-        {
-            Ok(stream) => stream,
-            Err(err) => return Err(Box::new(err)),
-        };
+        // The output format would prevent tool calls, so it is only set on requests without tools
+        let mut formatted = self.output_format.is_none() || !has_tools;
+        let mut stream = send_chat_stream(
+            &self.ollama,
+            &self.http_client,
+            self.history.get_history(),
+            request,
+            if formatted {
+                &format_extra_fields
+            } else {
+                &extra_fields
+            },
+            keep_thinking,
+        )
+        .await?;
 
         let (tx, rx) = mpsc::channel(32);
 
-        let tool_manager = self.tool_manager.clone();
-
         let history = self.history.clone();
-        let tool_confirmation = self.settings_manager.lock().unwrap().tool_confirmation;
-        let verbose_tool_calls = self.settings_manager.lock().unwrap().verbose_tool_calls;
+        let ollama = self.ollama.clone();
+        let http_client = self.http_client.clone();
+        let tool_call_context = {
+            let settings = self.settings_manager.lock().unwrap();
+            ToolCallContext {
+                tool_manager: self.tool_manager.clone(),
+                last_tool_result: self.last_tool_result.clone(),
+                session_allowed_tools: self.session_allowed_tools.clone(),
+                settings_manager: self.settings_manager.clone(),
+                batch_mode: self.batch_mode,
+                assume_yes: self.assume_yes,
+                audit_log: settings.audit_log.then(|| self.audit_log.clone()),
+                cache_ttl: settings
+                    .tool_result_cache
+                    .then(|| Duration::from_secs(settings.tool_cache_ttl)),
+                verbose: settings.verbose_tool_calls,
+                confirmation: settings.tool_confirmation,
+                result_max_length: settings.tool_result_max_length,
+                max_parallel_calls: settings.max_parallel_tool_calls,
+            }
+        };
+        let (max_tool_rounds, max_identical_tool_calls) = {
+            let settings = self.settings_manager.lock().unwrap();
+            (settings.max_tool_rounds, settings.max_identical_tool_calls)
+        };
         tokio::spawn(async move {
-            while let Some(Ok(res)) = stream.next().await {
-                {
-                    let mut history_guard = history.messages.lock().unwrap();
-                    history_guard.push(res.message.clone());
+            let mut tool_rounds = 0;
+            let mut call_counts: HashMap<String, usize> = HashMap::new();
+            let mut tools_allowed = true;
+            let mut streamed_text = String::new();
+            let mut streamed_thinking = String::new();
+
+            while let Some(res) = stream.next().await {
+                if let Some(final_data) = &res.final_data {
+                    history
+                        .record_token_count(final_data.prompt_eval_count + final_data.eval_count);
+                }
+
+                if let Some(thinking) = &res.message.thinking {
+                    streamed_thinking.push_str(thinking);
                 }
 
-                if !res.message.tool_calls.is_empty() {
-                    let mut tool_messages = Vec::new();
+                let tool_calls = if prompt_tools {
+                    streamed_text.push_str(&res.message.content);
+                    if res.done {
+                        parse_tool_calls(&streamed_text, &tools)
+                    } else {
+                        Vec::new()
+                    }
+                } else {
+                    res.message.tool_calls.clone()
+                };
+
+                if !formatted && (tool_calls.is_empty() || !tools_allowed) {
+                    // The model answered a request with tools, the answer is requested again in
+                    // the output format instead of being sent
+                    if res.done {
+                        history.messages.lock().unwrap().pop();
+                        streamed_text.clear();
+                        streamed_thinking.clear();
 
-                    for call in res.message.tool_calls {
-                        let args = match serde_json::json!(call.function.arguments)
-                            .as_object()
-                            .cloned()
+                        let request = ChatMessageRequest::new(model_name.clone(), vec![])
+                            .options(model_options.clone());
+                        stream = match send_chat_stream(
+                            &ollama,
+                            &http_client,
+                            history.get_history(),
+                            request,
+                            &format_extra_fields,
+                            keep_thinking,
+                        )
+                        .await
                         {
-                            Some(args) => args,
-                            None => continue,
+                            Ok(s) => s,
+                            Err(err) => {
+                                eprintln!("Failed to request the formatted answer: {}", err);
+                                break;
+                            }
                         };
+                        formatted = true;
+                    }
+                    continue;
+                }
 
-                        let mut stdout = stdout();
-                        if verbose_tool_calls || tool_confirmation {
-                            stdout
-                                .write_all(
-                                    format!(
-                                        "{}\n",
-                                        render_tool_call_request(
-                                            call.function.name.clone(),
-                                            args.clone()
-                                        )
-                                    )
-                                    .as_bytes(),
-                                )
-                                .await
-                                .unwrap();
-                            stdout.flush().await.unwrap();
+                if !tool_calls.is_empty() && tools_allowed {
+                    if prompt_tools {
+                        streamed_text.clear();
+                        if tool_call_context.shows_calls() {
+                            write_output("").await;
+                        }
+                    } else {
+                        // The history stream only records the text of the answers
+                        let mut message = res.message.clone();
+                        if keep_thinking && !streamed_thinking.is_empty() {
+                            message.thinking = Some(streamed_thinking.clone());
                         }
+                        history.messages.lock().unwrap().push(message);
+                    }
+                    streamed_thinking.clear();
 
-                        let mut call_tool = true;
-                        if tool_confirmation {
-                            let confirm = input::menu_selection(
-                                "Confirm tool call : ",
-                                vec![
-                                    MenuChoice {
-                                        name: "Yes".to_string(),
-                                        shortcut: 'Y',
-                                    },
-                                    MenuChoice {
-                                        name: "No".to_string(),
-                                        shortcut: 'N',
-                                    },
-                                ],
-                                false,
-                            )
-                            .await;
+                    tool_rounds += 1;
 
-                            if confirm == 1 {
-                                call_tool = false;
-                            }
-                        }
+                    let mut repeated_call = None;
+                    for call in &tool_calls {
+                        let count = call_counts
+                            .entry(format!(
+                                "{}:{}",
+                                call.function.name,
+                                canonical_json(&call.function.arguments)
+                            ))
+                            .or_insert(0);
+                        *count += 1;
 
-                        if call_tool {
-                            match tool_manager
-                                .lock()
-                                .await
-                                .call_tool(call.function.name.clone(), args)
-                                .await
-                            {
-                                Ok(result) => {
-                                    if verbose_tool_calls || tool_confirmation {
-                                        stdout
-                                            .write_all(
-                                                format!(
-                                                    "{}\n",
-                                                    render_tool_call_result(&result.content)
-                                                )
-                                                .as_bytes(),
-                                            )
-                                            .await
-                                            .unwrap();
-                                        stdout.flush().await.unwrap();
-                                    }
-
-                                    tool_messages.push(ChatMessage::tool(
-                                        serde_json::to_string(&result.content).unwrap_or_default(),
-                                    ));
-                                }
-                                Err(err) => {
-                                    eprintln!(
-                                        "Error calling tool {}: {:?}",
-                                        call.function.name, err
-                                    );
-                                    continue;
-                                }
-                            }
-                        } else {
-                            tool_messages
-                                .push(ChatMessage::tool("Tool cancelled by user".to_string()));
+                        if max_identical_tool_calls > 0 && *count > max_identical_tool_calls {
+                            repeated_call = Some(call.function.name.clone());
                         }
                     }
 
+                    let limit_reason = if max_tool_rounds > 0 && tool_rounds > max_tool_rounds {
+                        Some(format!(
+                            "Maximum number of tool rounds ({}) reached",
+                            max_tool_rounds
+                        ))
+                    } else {
+                        repeated_call.map(|name| {
+                            format!(
+                                "Tool {} was called more than {} times with the same arguments",
+                                name, max_identical_tool_calls
+                            )
+                        })
+                    };
+
+                    let tool_messages = match &limit_reason {
+                        Some(reason) => {
+                            eprintln!(
+                                "{}",
+                                colorize_text(
+                                    &format!(
+                                        "{}, stopping tool calls and asking the model for a final answer",
+                                        reason
+                                    ),
+                                    AnsiColor::BrightYellow
+                                )
+                            );
+                            tools_allowed = false;
+
+                            let content = serde_json::json!({
+                                "error": format!("Tool call not executed: {}", reason),
+                                "hint": "Do not call any more tools. Answer the user with the information you already have.",
+                            })
+                            .to_string();
+                            tool_calls
+                                .iter()
+                                .map(|_| ChatMessage::tool(content.clone()))
+                                .collect()
+                        }
+                        None => run_tool_calls(tool_calls.clone(), &tool_call_context).await,
+                    };
+
                     {
                         let mut history_guard = history.messages.lock().unwrap();
-                        for msg in &tool_messages {
-                            history_guard.push(msg.clone());
+                        if prompt_tools {
+                            history_guard.push(render_tool_results(&tool_calls, &tool_messages));
+                        } else {
+                            history_guard.extend(tool_messages);
                         }
                     }
 
-                    let mut request = ChatMessageRequest::new(model_name.clone(), tool_messages)
+                    // Tool messages are already in the history, which is sent as is
+                    let mut request = ChatMessageRequest::new(model_name.clone(), vec![])
                         .options(model_options.clone());
-                    if tools_capability {
+                    if tools_capability && tools_allowed {
                         request = request.tools(tools.clone());
                     }
+                    formatted = formatted || !tools_allowed;
 
-                    if thinking_capability {
-                        request = request.think(true);
-                    }
-
-                    let followup_stream = match Ollama::default()
-                        .send_chat_messages_with_history_stream(history.get_history(), request)
-                        .await
+                    let followup_stream = match send_chat_stream(
+                        &ollama,
+                        &http_client,
+                        history.get_history(),
+                        request,
+                        if formatted {
+                            &format_extra_fields
+                        } else {
+                            &extra_fields
+                        },
+                        keep_thinking,
+                    )
+                    .await
                     {
                         Ok(s) => s,
                         Err(err) => {
-                            eprintln!("Failed to send tool response back to Ollama: {:?}", err);
+                            eprintln!("Failed to send tool response back to Ollama: {}", err);
                             break;
                         }
                     };
@@ -299,6 +449,340 @@ impl OllamaChat {
         Ok(rx)
     }
 
+    /// Asks the model again for its answer in a structured format, with Ollama's `format` parameter
+    /// (`"json"` or a JSON schema), when the previous one didn't conform. Tools are not sent, so that
+    /// the model answers right away.
+    pub async fn format_answer(&self, format: &Value, instruction: String) -> AppResult<String> {
+        let model_name = self.settings_manager.lock().unwrap().model_name.clone();
+        let model_details = self
+            .model_cache
+            .get(&self.ollama, &self.http_client, &model_name)
+            .await?;
+        let model_settings = self.settings_manager.lock().unwrap().with_model_overrides();
+
+        let mut extra_fields =
+            request_extra_fields(&model_settings, model_details.has_capability("thinking"));
+        extra_fields.insert("format".to_string(), format.clone());
+
+        let request = ChatMessageRequest::new(model_name, vec![ChatMessage::user(instruction)])
+            .options(model_settings.model_options());
+        let mut stream = send_chat_stream(
+            &self.ollama,
+            &self.http_client,
+            self.history.get_history(),
+            request,
+            &extra_fields,
+            model_settings.keep_thinking_history,
+        )
+        .await?;
+
+        let mut answer = String::new();
+        while let Some(res) = stream.next().await {
+            answer.push_str(&res.message.content);
+        }
+
+        Ok(answer)
+    }
+
+    /// Tools given to the model for the conversation continued by `messages`, with the current
+    /// settings. The second value is set when they are described in the system prompt, for models
+    /// without native tool calling.
+    pub async fn model_tools(&self, messages: &[ChatMessage]) -> AppResult<(Vec<ToolInfo>, bool)> {
+        let model_name = self.settings_manager.lock().unwrap().model_name.clone();
+        let model_details = self
+            .model_cache
+            .get(&self.ollama, &self.http_client, &model_name)
+            .await?;
+        let tools_capability = model_details.has_capability("tools");
+
+        // Models without native tool calling get the tools described in the system prompt
+        let prompt_tools = !tools_capability
+            && self
+                .settings_manager
+                .lock()
+                .unwrap()
+                .with_model_overrides()
+                .tool_call_fallback;
+        if !tools_capability && !prompt_tools {
+            return Ok((Vec::new(), false));
+        }
+
+        let enabled_tools = self.tool_manager.lock().await.get_enabled_tools();
+        let (simplify, description_max_length) = {
+            let settings = self.settings_manager.lock().unwrap();
+            (
+                settings.simplify_tool_schemas,
+                settings.tool_description_max_length,
+            )
+        };
+        let tools: Vec<ToolInfo> = self
+            .select_tools(enabled_tools, messages)
+            .await
+            .iter()
+            .map(|t| t.to_model_tool_info(simplify, description_max_length))
+            .collect();
+        let prompt_tools = prompt_tools && !tools.is_empty();
+
+        Ok((tools, prompt_tools))
+    }
+
+    /// Keeps the `tool_retrieval_top_k` tools most relevant to the conversation, plus the pinned tools.
+    ///
+    /// Every tool is kept when tool retrieval is disabled or fails.
+    async fn select_tools(&self, tools: Vec<MCPTool>, messages: &[ChatMessage]) -> Vec<MCPTool> {
+        let (enabled, model, top_k, pinned_tools, verbose) = {
+            let settings = self.settings_manager.lock().unwrap();
+            (
+                settings.tool_retrieval,
+                settings.tool_retrieval_model.clone(),
+                settings.tool_retrieval_top_k,
+                settings.pinned_tools.clone(),
+                settings.verbose_tool_calls,
+            )
+        };
+
+        if !enabled || tools.len() <= top_k {
+            return tools;
+        }
+
+        // The last user messages describe what the conversation is currently about
+        let query = {
+            let history = self.history.messages.lock().unwrap();
+            let user_messages: Vec<&str> = history
+                .iter()
+                .chain(messages.iter())
+                .filter(|m| m.role == MessageRole::User)
+                .map(|m| m.content.as_str())
+                .collect();
+            user_messages[user_messages.len().saturating_sub(3)..].join("\n")
+        };
+
+        let ranked_tools = match self
+            .tool_retriever
+            .lock()
+            .await
+            .rank_tools(&self.ollama, &model, &tools, &query, top_k)
+            .await
+        {
+            Ok(ranked_tools) => ranked_tools,
+            Err(err) => {
+                eprintln!(
+                    "{}",
+                    colorize_text(
+                        &format!("Tool retrieval failed, sending every tool: {}", err),
+                        AnsiColor::BrightYellow
+                    )
+                );
+                return tools;
+            }
+        };
+
+        let total_count = tools.len();
+        let selected_tools: Vec<MCPTool> = {
+            let tool_manager = self.tool_manager.lock().await;
+            tools
+                .into_iter()
+                .filter(|tool| {
+                    let name = tool.tool_info.name.as_ref();
+                    ranked_tools.iter().any(|t| t == name)
+                        || pinned_tools
+                            .iter()
+                            .any(|pattern| tool_manager.tool_matches(pattern, name))
+                })
+                .collect()
+        };
+
+        if verbose {
+            eprintln!(
+                "{}",
+                colorize_text(
+                    &format!(
+                        "Sending {} of {} tools: {}",
+                        selected_tools.len(),
+                        total_count,
+                        selected_tools
+                            .iter()
+                            .map(|t| t.tool_info.name.to_string())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ),
+                    AnsiColor::BrightBlack
+                )
+            );
+        }
+
+        selected_tools
+    }
+
+    /// Forgets the cached details of a model, after it was selected or pulled
+    pub fn invalidate_model_info(&self, model_name: &str) {
+        self.model_cache.invalidate(model_name);
+    }
+
+    /// Replaces the conversation with a summary written by the model, keeping the system prompt.
+    ///
+    /// Returns the estimated token count of the history before and after.
+    pub async fn compact(&self, instructions: Option<&str>) -> AppResult<(u64, u64)> {
+        let model_name = self.settings_manager.lock().unwrap().model_name.clone();
+        let model_details = self
+            .model_cache
+            .get(&self.ollama, &self.http_client, &model_name)
+            .await?;
+        let model_settings = self.settings_manager.lock().unwrap().with_model_overrides();
+        let extra_fields =
+            request_extra_fields(&model_settings, model_details.has_capability("thinking"));
+
+        let tokens_before = self.history.estimated_tokens();
+        let messages = self.history.messages.lock().unwrap().clone();
+        let turns = split_turns(&messages);
+        let Some(first_turn) = turns.first() else {
+            return Err("The conversation is empty, there is nothing to compact".into());
+        };
+        let compacted_range = first_turn.start..messages.len();
+
+        let summary = summarize_messages(
+            &self.ollama,
+            &self.http_client,
+            ChatMessageRequest::new(model_name, vec![]).options(model_settings.model_options()),
+            &extra_fields,
+            &messages[compacted_range.clone()],
+            instructions,
+        )
+        .await?;
+
+        self.history
+            .messages
+            .lock()
+            .unwrap()
+            .splice(compacted_range, [summary]);
+        self.history.reset_token_count();
+
+        Ok((tokens_before, self.history.estimated_tokens()))
+    }
+
+    /// Applies the context strategy when the history and the new messages use more than
+    /// `context_threshold` percent of the context window
+    async fn manage_context(
+        &self,
+        model_settings: &SettingsManager,
+        context_length: Option<u64>,
+        new_messages: &[ChatMessage],
+        summary_request: ChatMessageRequest,
+        extra_fields: &Map<String, Value>,
+    ) {
+        let context_window = model_settings
+            .num_ctx
+            .unwrap_or(OLLAMA_DEFAULT_NUM_CTX)
+            .min(context_length.unwrap_or(u64::MAX));
+        let budget = context_window * model_settings.context_threshold / 100;
+        let used_tokens =
+            self.history.estimated_tokens() + new_messages.iter().map(estimate_tokens).sum::<u64>();
+
+        if used_tokens <= budget {
+            return;
+        }
+
+        let messages = self.history.messages.lock().unwrap().clone();
+        let turns = split_turns(&messages);
+        // The kept turns use at most half of the budget, so that the strategy doesn't apply again on the next message
+        let reduced_turns = turns_to_reduce(&messages, &turns, budget / 2);
+
+        if model_settings.context_strategy == ContextStrategy::Warn || reduced_turns == 0 {
+            eprintln!(
+                "{}",
+                colorize_text(
+                    &format!(
+                        "The conversation uses about {} of the {} tokens of the context window, the model may ignore its oldest messages (use /compact or /clear to reduce it)",
+                        used_tokens, context_window
+                    ),
+                    AnsiColor::BrightYellow
+                )
+            );
+            return;
+        }
+
+        let reduced_range = turns[0].start..turns[reduced_turns - 1].end;
+        let replacement = match model_settings.context_strategy {
+            ContextStrategy::Summarize => {
+                match summarize_messages(
+                    &self.ollama,
+                    &self.http_client,
+                    summary_request,
+                    extra_fields,
+                    &messages[reduced_range.clone()],
+                    None,
+                )
+                .await
+                {
+                    Ok(summary) => vec![summary],
+                    Err(err) => {
+                        eprintln!(
+                            "{}",
+                            colorize_text(
+                                &format!("Unable to summarize the oldest messages: {}", err),
+                                AnsiColor::BrightRed
+                            )
+                        );
+                        return;
+                    }
+                }
+            }
+            _ => Vec::new(),
+        };
+
+        self.history
+            .messages
+            .lock()
+            .unwrap()
+            .splice(reduced_range, replacement);
+        self.history.reset_token_count();
+
+        eprintln!(
+            "{}",
+            colorize_text(
+                &format!(
+                    "{} the oldest {} turn(s) of the conversation to fit in the context window of {} tokens",
+                    if model_settings.context_strategy == ContextStrategy::Summarize {
+                        "Summarized"
+                    } else {
+                        "Dropped"
+                    },
+                    reduced_turns,
+                    context_window
+                ),
+                AnsiColor::BrightBlack
+            )
+        );
+    }
+
+    /// Keeps the system prompt as the first message of the history, removing it when empty
+    fn set_system_prompt(&self, system_prompt: String) {
+        let mut history = self.history.messages.lock().unwrap();
+        let has_system_message = history
+            .first()
+            .is_some_and(|m| m.role == MessageRole::System);
+
+        match (has_system_message, system_prompt.is_empty()) {
+            (true, true) => {
+                history.remove(0);
+            }
+            (true, false) => history[0].content = system_prompt,
+            (false, false) => history.insert(0, ChatMessage::system(system_prompt)),
+            (false, true) => {}
+        }
+    }
+
+    /// Runs without user interaction: tool calls the policy asks about are denied unless `assume_yes` is set
+    pub fn set_batch_mode(&mut self, assume_yes: bool) {
+        self.batch_mode = true;
+        self.assume_yes = assume_yes;
+    }
+
+    /// Sets the Ollama `format` parameter of the final answers, `None` for free text
+    pub fn set_output_format(&mut self, format: Option<Value>) {
+        self.output_format = format;
+    }
+
     pub fn clear(&mut self) {
         let _ = self.history.clear_messages();
     }
@@ -306,4 +790,575 @@ impl OllamaChat {
     pub fn get_history(&self) -> Arc<Mutex<Vec<ChatMessage>>> {
         return self.history.get_history();
     }
+
+    /// Records a tool call made outside of the model (e.g. with `/tools call`) in the chat history
+    pub async fn add_tool_call_to_history(
+        &self,
+        name: String,
+        arguments: Map<String, Value>,
+        result: &CallToolResult,
+    ) {
+        let result_limit = self
+            .tool_manager
+            .lock()
+            .await
+            .get_result_limit(&name)
+            .unwrap_or(self.settings_manager.lock().unwrap().tool_result_max_length);
+        let (content, _) = truncate_tool_result(
+            serde_json::to_string(&result.content).unwrap_or_default(),
+            result_limit,
+        );
+
+        let mut call_message = ChatMessage::assistant(String::new());
+        call_message.tool_calls.push(ToolCall {
+            function: ToolCallFunction {
+                name,
+                arguments: Value::Object(arguments),
+            },
+        });
+
+        let mut history_guard = self.history.messages.lock().unwrap();
+        history_guard.push(call_message);
+        history_guard.push(ChatMessage::tool(content));
+    }
+
+    pub fn get_last_tool_result(&self) -> Option<String> {
+        self.last_tool_result.lock().unwrap().clone()
+    }
+}
+
+/// Fields added to the body of every chat request, as ollama-rs doesn't support thinking levels and min_p
+fn request_extra_fields(
+    model_settings: &SettingsManager,
+    thinking_capability: bool,
+) -> Map<String, Value> {
+    let mut extra_fields = Map::new();
+    if thinking_capability {
+        extra_fields.insert("think".to_string(), model_settings.think.to_request_value());
+    }
+    if let Some(min_p) = model_settings.min_p {
+        extra_fields.insert("options".to_string(), serde_json::json!({ "min_p": min_p }));
+    }
+    if let Some(keep_alive) = model_settings.keep_alive() {
+        extra_fields.insert("keep_alive".to_string(), keep_alive);
+    }
+
+    extra_fields
+}
+
+/// Everything needed to run the tool calls of a chat request, with the settings captured when it started
+#[derive(Debug, Clone)]
+struct ToolCallContext {
+    tool_manager: Arc<tokio::sync::Mutex<ToolManager>>,
+    last_tool_result: Arc<Mutex<Option<String>>>,
+    session_allowed_tools: Arc<Mutex<HashSet<String>>>,
+    settings_manager: Arc<Mutex<SettingsManager>>,
+    batch_mode: bool,
+    assume_yes: bool,
+    audit_log: Option<Arc<AuditLog>>,
+    /// Validity of cached tool results, `None` when the cache is disabled
+    cache_ttl: Option<Duration>,
+    verbose: bool,
+    confirmation: bool,
+    result_max_length: usize,
+    max_parallel_calls: usize,
+}
+
+/// Approval of a tool call, with what made the decision as recorded in the audit log
+enum Approval {
+    Allowed(&'static str),
+    Denied(&'static str, String),
+    Confirm,
+}
+
+impl ToolCallContext {
+    /// Tool calls are shown when verbose, or when they may need a confirmation from the user
+    fn shows_calls(&self) -> bool {
+        self.verbose || (self.confirmation && !self.batch_mode)
+    }
+
+    /// Decides whether a tool call can run, checking the policy first, then the confirmation settings
+    async fn approval(&self, name: &str, arguments: &Map<String, Value>) -> Approval {
+        let policy_action = self
+            .tool_manager
+            .lock()
+            .await
+            .evaluate_policy(name, arguments);
+
+        let allowed_by_user = if self.session_allowed_tools.lock().unwrap().contains(name) {
+            Some("session")
+        } else if self
+            .settings_manager
+            .lock()
+            .unwrap()
+            .always_allowed_tools
+            .iter()
+            .any(|t| t == name)
+        {
+            Some("always_allowed")
+        } else {
+            None
+        };
+
+        // Without a matching policy rule, `tool_confirmation` asks like a policy would
+        let policy_action = policy_action.or(self.confirmation.then_some(PolicyAction::Ask));
+
+        match (policy_action, allowed_by_user) {
+            (Some(PolicyAction::Deny), _) => {
+                Approval::Denied("policy", "Tool call denied by policy".to_string())
+            }
+            (Some(PolicyAction::Allow), _) => Approval::Allowed("policy"),
+            (Some(PolicyAction::Ask), Some(decided_by)) => Approval::Allowed(decided_by),
+            (Some(PolicyAction::Ask), None) if self.batch_mode && self.assume_yes => {
+                Approval::Allowed("--yes")
+            }
+            (Some(PolicyAction::Ask), None) if self.batch_mode => Approval::Denied(
+                "batch_mode",
+                "Tool call requires approval, which is not possible in batch mode".to_string(),
+            ),
+            (Some(PolicyAction::Ask), None) => Approval::Confirm,
+            (None, Some(decided_by)) => Approval::Allowed(decided_by),
+            (None, None) => Approval::Allowed("settings"),
+        }
+    }
+}
+
+/// Runs the tool calls requested in a model message and returns their tool messages in the original order.
+///
+/// Consecutive calls that need no confirmation run concurrently, unless they target the same server
+/// and one of them is not read-only.
+async fn run_tool_calls(calls: Vec<ToolCall>, context: &ToolCallContext) -> Vec<ChatMessage> {
+    let semaphore = Semaphore::new(context.max_parallel_calls.max(1));
+    let mut tool_messages = Vec::with_capacity(calls.len());
+    let mut batch: Vec<(ToolCall, Option<String>, bool)> = Vec::new();
+
+    for call in calls {
+        let (server, read_only) = {
+            let tool_manager = context.tool_manager.lock().await;
+            (
+                tool_manager.get_tool_server(&call.function.name),
+                tool_manager
+                    .get_tool(&call.function.name)
+                    .is_some_and(|tool| tool.is_read_only()),
+            )
+        };
+
+        let conflicts = batch.iter().any(|(_, batch_server, batch_read_only)| {
+            *batch_server == server && !(read_only && *batch_read_only)
+        });
+
+        let requires_confirmation = match call.function.arguments.as_object() {
+            Some(arguments) => matches!(
+                context.approval(&call.function.name, arguments).await,
+                Approval::Confirm
+            ),
+            None => false,
+        };
+
+        if requires_confirmation || conflicts {
+            tool_messages.extend(run_batch(&batch, &semaphore, context).await);
+            batch.clear();
+        }
+
+        if requires_confirmation {
+            tool_messages.push(process_tool_call(&call, context).await);
+        } else {
+            batch.push((call, server, read_only));
+        }
+    }
+
+    tool_messages.extend(run_batch(&batch, &semaphore, context).await);
+
+    tool_messages
+}
+
+async fn run_batch(
+    batch: &[(ToolCall, Option<String>, bool)],
+    semaphore: &Semaphore,
+    context: &ToolCallContext,
+) -> Vec<ChatMessage> {
+    join_all(batch.iter().map(|(call, _, _)| async move {
+        let _permit = semaphore.acquire().await;
+        process_tool_call(call, context).await
+    }))
+    .await
+}
+
+/// Runs a single tool call requested by the model and records it in the audit log
+async fn process_tool_call(call: &ToolCall, context: &ToolCallContext) -> ChatMessage {
+    let Some(audit_log) = &context.audit_log else {
+        return execute_tool_call(call, context, &mut None).await;
+    };
+
+    let mut record = AuditRecord::new(
+        &audit_log.session_id,
+        &call.function.name,
+        call.function.arguments.clone(),
+    );
+    record.server = context
+        .tool_manager
+        .lock()
+        .await
+        .get_tool_server(&call.function.name);
+
+    let mut audit_record = Some(record);
+    let message = execute_tool_call(call, context, &mut audit_record).await;
+
+    if let Some(mut record) = audit_record {
+        record.set_result(&message.content);
+        audit_log.record(&record).unwrap_or_else(|err| {
+            eprintln!("Error writing to the audit log: {}", err);
+        });
+    }
+
+    message
+}
+
+/// Validates, confirms and runs a single tool call requested by the model.
+///
+/// Always returns the tool message answering the call, describing the error when it failed.
+async fn execute_tool_call(
+    call: &ToolCall,
+    context: &ToolCallContext,
+    audit_record: &mut Option<AuditRecord>,
+) -> ChatMessage {
+    let name = call.function.name.clone();
+    let show_calls = context.shows_calls();
+
+    let args = match call.function.arguments.as_object() {
+        Some(args) => args.clone(),
+        None => {
+            return tool_error_message(
+                &name,
+                &format!(
+                    "Arguments must be a JSON object, got {}",
+                    call.function.arguments
+                ),
+                show_calls,
+            )
+            .await;
+        }
+    };
+
+    let tool = match context
+        .tool_manager
+        .lock()
+        .await
+        .get_tool(&name)
+        .filter(|tool| tool.enabled)
+    {
+        Some(tool) => tool,
+        None => {
+            return tool_error_message(
+                &name,
+                &format!("Tool '{}' does not exist or is disabled", name),
+                show_calls,
+            )
+            .await;
+        }
+    };
+
+    let mut args = match validate_arguments(&tool.tool_info.input_schema, args) {
+        Ok(args) => args,
+        Err(errors) => {
+            if show_calls {
+                write_output(&render_tool_call_error(
+                    &name,
+                    &format_validation_errors(&errors),
+                ))
+                .await;
+            }
+
+            return ChatMessage::tool(render_validation_errors(&name, &errors));
+        }
+    };
+
+    let requires_confirmation = match context.approval(&name, &args).await {
+        Approval::Allowed(decided_by) => {
+            set_audit_decision(audit_record, true, decided_by);
+            false
+        }
+        Approval::Confirm => true,
+        Approval::Denied(decided_by, reason) => {
+            set_audit_decision(audit_record, false, decided_by);
+            if decided_by == "batch_mode" {
+                eprintln!(
+                    "{}",
+                    colorize_text(
+                        &format!(
+                            "Tool call {} denied: it requires approval, pass --yes or add a policy rule allowing it",
+                            name
+                        ),
+                        AnsiColor::BrightYellow
+                    )
+                );
+            }
+            return tool_error_message(&name, &reason, show_calls).await;
+        }
+    };
+
+    if show_calls || requires_confirmation {
+        write_output(&render_tool_call_request(name.clone(), args.clone())).await;
+    }
+
+    let mut edited = false;
+    if requires_confirmation {
+        loop {
+            let confirm = input::menu_selection(
+                "Confirm tool call : ",
+                vec![
+                    MenuChoice {
+                        name: "Yes".to_string(),
+                        shortcut: 'Y',
+                    },
+                    MenuChoice {
+                        name: "Edit arguments".to_string(),
+                        shortcut: 'E',
+                    },
+                    MenuChoice {
+                        name: "Allow for this session".to_string(),
+                        shortcut: 'S',
+                    },
+                    MenuChoice {
+                        name: "Always allow this tool".to_string(),
+                        shortcut: 'A',
+                    },
+                    MenuChoice {
+                        name: "No".to_string(),
+                        shortcut: 'N',
+                    },
+                    MenuChoice {
+                        name: "Deny with message".to_string(),
+                        shortcut: 'D',
+                    },
+                ],
+                true,
+            )
+            .await;
+
+            match confirm {
+                1 => {
+                    let edited_args = input::edit_text(
+                        &serde_json::to_string_pretty(&args).unwrap_or_default(),
+                        "json",
+                    );
+
+                    let edit_error = match serde_json::from_str::<Value>(&edited_args) {
+                        Ok(Value::Object(edited_args)) => {
+                            match validate_arguments(&tool.tool_info.input_schema, edited_args) {
+                                Ok(edited_args) => {
+                                    edited = edited || edited_args != args;
+                                    args = edited_args;
+                                    None
+                                }
+                                Err(errors) => Some(format_validation_errors(&errors)),
+                            }
+                        }
+                        Ok(_) => Some("Arguments must be a JSON object".to_string()),
+                        Err(err) => Some(format!("Invalid JSON: {}", err)),
+                    };
+
+                    match edit_error {
+                        Some(error) => write_output(&render_tool_call_error(&name, &error)).await,
+                        None => {
+                            write_output(&render_tool_call_request(name.clone(), args.clone()))
+                                .await
+                        }
+                    }
+                    continue;
+                }
+                2 => {
+                    context
+                        .session_allowed_tools
+                        .lock()
+                        .unwrap()
+                        .insert(name.clone());
+                }
+                3 => {
+                    let mut settings = context.settings_manager.lock().unwrap();
+                    settings.always_allowed_tools.push(name.clone());
+                    if settings.auto_save_config {
+                        settings
+                            .save_to_file(&settings.config_file_path)
+                            .unwrap_or_else(|err| {
+                                eprintln!("Error saving settings: {}", err);
+                            });
+                    }
+                }
+                4 => {
+                    set_audit_decision(audit_record, false, "user");
+                    return ChatMessage::tool("Tool cancelled by user".to_string());
+                }
+                5 => {
+                    set_audit_decision(audit_record, false, "user");
+                    let reason = input::text_input("Message for the model: ");
+                    return ChatMessage::tool(format!("Tool call denied by user: {}", reason));
+                }
+                _ => {}
+            }
+            break;
+        }
+        set_audit_decision(audit_record, true, "user");
+    }
+
+    if let Some(record) = audit_record {
+        record.arguments = Value::Object(args.clone());
+    }
+
+    // Tell the model which arguments were actually used
+    let edit_note = if edited {
+        format!(
+            "[The user edited the arguments of this call before running it. Arguments used: {}]\n",
+            Value::Object(args.clone())
+        )
+    } else {
+        String::new()
+    };
+
+    let cached_result = match context.cache_ttl {
+        Some(ttl) => context
+            .tool_manager
+            .lock()
+            .await
+            .get_cached_result(&name, &args, ttl),
+        None => None,
+    };
+    let cached = cached_result.is_some();
+
+    let started_at = Instant::now();
+    let call_result = match cached_result {
+        Some(result) => Ok(result),
+        None => {
+            let peer = context.tool_manager.lock().await.get_tool_peer(&name);
+            match peer {
+                Some(peer) => {
+                    ToolManager::call_tool_with_peer(peer, name.clone(), args.clone()).await
+                }
+                None => Err(format!("Tool '{}' not found.", name).into()),
+            }
+        }
+    };
+
+    if let Some(record) = audit_record {
+        record.duration_ms = Some(started_at.elapsed().as_millis() as u64);
+        record.cached = cached;
+        record.is_error = call_result
+            .as_ref()
+            .map_or(true, |result| result.is_error.unwrap_or(false));
+    }
+
+    if context.cache_ttl.is_some() && !cached {
+        let mut tool_manager = context.tool_manager.lock().await;
+        tool_manager.invalidate_cached_results(&name);
+        if let Ok(result) = &call_result {
+            tool_manager.cache_result(&name, &args, result);
+        }
+    }
+
+    let result = match call_result {
+        Ok(result) => result,
+        Err(err) => return tool_error_message(&name, &err.to_string(), show_calls).await,
+    };
+
+    let is_error = result.is_error.unwrap_or(false);
+    let full_result = format_tool_result_content(&result.content);
+
+    if show_calls {
+        if is_error {
+            write_output(&render_tool_call_error(&name, &full_result)).await;
+        } else {
+            write_output(&render_tool_call_result(&result.content)).await;
+        }
+
+        if cached {
+            write_output(&colorize_text(
+                "Result reused from the tool result cache (use /tools cache clear to refresh it)",
+                AnsiColor::BrightBlack,
+            ))
+            .await;
+        }
+    }
+
+    *context.last_tool_result.lock().unwrap() = Some(full_result);
+
+    let result_limit = context
+        .tool_manager
+        .lock()
+        .await
+        .get_result_limit(&name)
+        .unwrap_or(context.result_max_length);
+    let (content, truncated) = truncate_tool_result(
+        serde_json::to_string(&result.content).unwrap_or_default(),
+        result_limit,
+    );
+
+    if truncated && show_calls {
+        write_output(&colorize_text(
+            &format!(
+                "Result truncated to {} characters for the model (use /last-result to view it in full)",
+                result_limit
+            ),
+            AnsiColor::BrightBlack,
+        ))
+        .await;
+    }
+
+    if is_error {
+        ChatMessage::tool(format!(
+            "{}{}",
+            edit_note,
+            render_tool_error(&name, &content)
+        ))
+    } else {
+        ChatMessage::tool(format!("{}{}", edit_note, content))
+    }
+}
+
+fn set_audit_decision(audit_record: &mut Option<AuditRecord>, allowed: bool, decided_by: &str) {
+    if let Some(record) = audit_record {
+        record.set_decision(allowed, decided_by);
+    }
+}
+
+/// Reports a failed tool call to the user and builds the tool message describing it to the model
+async fn tool_error_message(name: &str, error: &str, show_calls: bool) -> ChatMessage {
+    if show_calls {
+        write_output(&render_tool_call_error(name, error)).await;
+    }
+
+    ChatMessage::tool(render_tool_error(name, error))
+}
+
+fn render_tool_error(name: &str, error: &str) -> String {
+    serde_json::json!({
+        "error": format!("Tool '{}' failed", name),
+        "details": error,
+    })
+    .to_string()
+}
+
+async fn write_output(text: &str) {
+    let mut stdout = stdout();
+    stdout
+        .write_all(format!("{}\n", text).as_bytes())
+        .await
+        .unwrap();
+    stdout.flush().await.unwrap();
+}
+
+/// Cuts a tool result down to `limit` characters (0 = unlimited) and marks where it was cut
+fn truncate_tool_result(content: String, limit: usize) -> (String, bool) {
+    let total_length = content.chars().count();
+    if limit == 0 || total_length <= limit {
+        return (content, false);
+    }
+
+    let truncated: String = content.chars().take(limit).collect();
+    (
+        format!(
+            "{}\n[... result truncated: showing {} of {} characters ...]",
+            truncated, limit, total_length
+        ),
+        true,
+    )
 }
@@ -0,0 +1,149 @@
+use std::{
+    ops::Range,
+    sync::{Arc, Mutex},
+};
+
+use ollama_rs::{
+    Ollama,
+    generation::chat::{ChatMessage, MessageRole, request::ChatMessageRequest},
+};
+use reqwest::Client;
+use serde_json::{Map, Value};
+use tokio_stream::StreamExt;
+
+use crate::{AppResult, ollama::send_chat_stream, tools::fallback::is_tool_results_message};
+
+/// Context window Ollama uses when `num_ctx` is not set
+pub const OLLAMA_DEFAULT_NUM_CTX: u64 = 4096;
+
+const SUMMARY_PROMPT: &str = "Summarize the following conversation between a user and an assistant, so that the assistant can continue it without the original messages. \
+Keep the goals and requests of the user, the decisions made, and the key facts and results obtained with tools (names, values, paths, identifiers). \
+Write only the summary, as concisely as possible.";
+
+/// Start of the message replacing the summarized part of the conversation
+const SUMMARY_PREFIX: &str = "Summary of the earlier conversation:";
+
+/// Rough token count of a message, for the messages no `prompt_eval_count` covers yet
+pub fn estimate_tokens(message: &ChatMessage) -> u64 {
+    let tool_calls_length: usize = message
+        .tool_calls
+        .iter()
+        .map(|call| call.function.name.len() + call.function.arguments.to_string().len())
+        .sum();
+    let length = message.content.len()
+        + message.thinking.as_ref().map_or(0, |t| t.len())
+        + tool_calls_length;
+
+    // About 4 characters per token, plus the role and separators of the chat template
+    (length / 4) as u64 + 4
+}
+
+/// Splits the history into turns, each starting at a user message, so that tool calls always stay
+/// with their results. The system prompt at the start of the history doesn't belong to any turn.
+pub fn split_turns(messages: &[ChatMessage]) -> Vec<Range<usize>> {
+    let start = usize::from(
+        messages
+            .first()
+            .is_some_and(|m| m.role == MessageRole::System),
+    );
+
+    let mut turns: Vec<Range<usize>> = Vec::new();
+    for (i, message) in messages.iter().enumerate().skip(start) {
+        let starts_turn = message.role == MessageRole::User && !is_tool_results_message(message);
+        match turns.last_mut() {
+            Some(turn) if !starts_turn => turn.end = i + 1,
+            _ => turns.push(i..i + 1),
+        }
+    }
+
+    turns
+}
+
+/// Returns how many of the oldest turns have to go for the rest of the history to fit in `budget`
+/// tokens. The last turn is always kept.
+pub fn turns_to_reduce(messages: &[ChatMessage], turns: &[Range<usize>], budget: u64) -> usize {
+    let mut kept_tokens: u64 = turns.first().map_or(0, |turn| {
+        messages[..turn.start].iter().map(estimate_tokens).sum()
+    });
+    let mut kept = 0;
+
+    for turn in turns.iter().rev() {
+        kept_tokens += messages[turn.clone()]
+            .iter()
+            .map(estimate_tokens)
+            .sum::<u64>();
+        if kept > 0 && kept_tokens > budget {
+            break;
+        }
+        kept += 1;
+    }
+
+    turns.len() - kept
+}
+
+/// Asks the model for a summary of the given messages, and returns the message replacing them
+pub async fn summarize_messages(
+    ollama: &Ollama,
+    http_client: &Client,
+    request: ChatMessageRequest,
+    extra_fields: &Map<String, Value>,
+    messages: &[ChatMessage],
+    instructions: Option<&str>,
+) -> AppResult<ChatMessage> {
+    let mut system_prompt = SUMMARY_PROMPT.to_string();
+    if let Some(instructions) = instructions {
+        system_prompt.push_str(&format!("\n\n{}", instructions));
+    }
+
+    let mut request = request;
+    request.messages = vec![
+        ChatMessage::system(system_prompt),
+        ChatMessage::user(render_transcript(messages)),
+    ];
+
+    // The summary request has its own history, the chat history is only replaced once it is done
+    let history = Arc::new(Mutex::new(Vec::new()));
+    let mut stream =
+        send_chat_stream(ollama, http_client, history, request, extra_fields, false).await?;
+
+    let mut summary = String::new();
+    while let Some(res) = stream.next().await {
+        summary.push_str(&res.message.content);
+    }
+
+    let summary = summary.trim();
+    if summary.is_empty() {
+        return Err("The model returned an empty summary".into());
+    }
+
+    Ok(ChatMessage::user(format!(
+        "{}\n{}",
+        SUMMARY_PREFIX, summary
+    )))
+}
+
+/// Renders messages as a plain text transcript, with the tool calls and their results
+fn render_transcript(messages: &[ChatMessage]) -> String {
+    let mut transcript = Vec::new();
+
+    for message in messages {
+        match message.role {
+            MessageRole::User => transcript.push(format!("User: {}", message.content)),
+            MessageRole::Assistant => {
+                if !message.content.is_empty() {
+                    transcript.push(format!("Assistant: {}", message.content));
+                }
+                for call in &message.tool_calls {
+                    transcript.push(format!(
+                        "Assistant called tool {} with {}",
+                        call.function.name, call.function.arguments
+                    ));
+                }
+            }
+            MessageRole::Tool => transcript.push(format!("Tool result: {}", message.content)),
+            MessageRole::System => transcript.push(format!("System: {}", message.content)),
+        }
+    }
+
+    transcript.join("\n\n")
+}
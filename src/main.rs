@@ -1,15 +1,28 @@
 mod args;
+mod audit;
 mod chat;
+mod context;
 mod model;
+mod ollama;
 mod settings;
 mod tools;
 mod ui;
 
 use ollama_rs::generation::chat::ChatMessage;
-
-use crate::{chat::OllamaChat, settings::SettingsManager, tools::ToolManager};
+use serde_json::{Map, Value};
+
+use crate::{
+    args::OutputFormat,
+    chat::OllamaChat,
+    settings::SettingsManager,
+    tools::{
+        ToolManager,
+        schema::{format_validation_errors, validate_json},
+    },
+};
 use std::{
-    path::PathBuf,
+    fs::read_to_string,
+    path::{Path, PathBuf},
     process::exit,
     sync::{Arc, Mutex},
 };
@@ -19,6 +32,9 @@ pub type AppResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
 pub enum ConfigFile {
     Settings,
     MCPServers,
+    ToolPolicy,
+    AuditLog,
+    ToolEmbeddings,
 }
 
 impl ConfigFile {
@@ -26,6 +42,9 @@ impl ConfigFile {
         match self {
             ConfigFile::Settings => "settings.json",
             ConfigFile::MCPServers => "mcp_servers.json",
+            ConfigFile::ToolPolicy => "tool_policy.json",
+            ConfigFile::AuditLog => "audit.jsonl",
+            ConfigFile::ToolEmbeddings => "tool_embeddings.json",
         }
     }
 }
@@ -46,6 +65,10 @@ pub fn get_config_path(file: ConfigFile) -> PathBuf {
 async fn main() -> AppResult<()> {
     let args = args::Args::parse();
 
+    if let Some(args::Command::Audit(audit_args)) = &args.command {
+        return audit::render_audit_log(&get_config_path(ConfigFile::AuditLog), audit_args);
+    }
+
     let json_config_path = if let Some(config_path) = args.config.clone() {
         PathBuf::from(&config_path)
     } else {
@@ -71,6 +94,10 @@ async fn main() -> AppResult<()> {
             }
         }
 
+        if let Some(ollama_host) = args.ollama_host.clone() {
+            settings_manager_lock.ollama_host = ollama_host;
+        }
+
         if settings_manager_lock.auto_save_config {
             settings_manager_lock
                 .save_to_file(&settings_manager_lock.config_file_path)
@@ -80,33 +107,73 @@ async fn main() -> AppResult<()> {
 
     let tool_manager = Arc::new(tokio::sync::Mutex::new(ToolManager::new(
         ToolManager::load_mcp_server_from_args(args.clone())?,
+        ToolManager::load_policy_from_args(&args)?,
     )));
 
     tool_manager.lock().await.initialize().await?;
 
-    let ollama_chat = OllamaChat::new(
-        Arc::clone(&tool_manager),
-        Arc::clone(&settings_manager),
-        args.ollama_host.clone(),
-    );
+    if let Some(toolset) = args.toolset.clone() {
+        let patterns = settings_manager
+            .lock()
+            .unwrap()
+            .toolsets
+            .get(&toolset)
+            .cloned()
+            .ok_or(format!("Unknown toolset '{}'", toolset))?;
+        tool_manager.lock().await.apply_toolset(&patterns);
+    }
+
+    let mut ollama_chat =
+        OllamaChat::new(Arc::clone(&tool_manager), Arc::clone(&settings_manager))?;
 
     if args.prompt.is_some() {
         settings_manager.lock().unwrap().verbose_tool_calls = false;
-        settings_manager.lock().unwrap().tool_confirmation = false;
+        ollama_chat.set_batch_mode(args.yes);
 
         let prompt = args.prompt.unwrap_or_else(|| {
             eprintln!("No prompt provided. Exiting.");
             exit(1);
         });
 
+        let schema = match &args.schema {
+            Some(path) => Some(load_schema(path)?),
+            None => None,
+        };
+        let format = match &schema {
+            Some(schema) => Some(Value::Object(schema.clone())),
+            None if args.format == Some(OutputFormat::Json) => {
+                Some(Value::String("json".to_string()))
+            }
+            None => None,
+        };
+        ollama_chat.set_output_format(format.clone());
+
         let mut output = String::new();
-        let mut stream = ollama_chat.chat(vec![ChatMessage::user(prompt)]).await?;
+        let mut stream = match ollama_chat.chat(vec![ChatMessage::user(prompt)]).await {
+            Ok(stream) => stream,
+            Err(err) => {
+                eprintln!("{}", err);
+                exit(1);
+            }
+        };
 
         while let Some(chunk) = stream.recv().await {
             output.push_str(&chunk.message.content);
         }
 
-        println!("{}", output);
+        if let Some(format) = format {
+            match get_structured_answer(&ollama_chat, output, format, schema, args.format_retries)
+                .await
+            {
+                Ok(answer) => println!("{}", answer),
+                Err(err) => {
+                    eprintln!("{}", err);
+                    exit(1);
+                }
+            }
+        } else {
+            println!("{}", output);
+        }
 
         return Ok(());
     }
@@ -116,3 +183,65 @@ async fn main() -> AppResult<()> {
 
     Ok(())
 }
+
+fn load_schema(path: &Path) -> AppResult<Map<String, Value>> {
+    let content = read_to_string(path)
+        .map_err(|err| format!("Unable to read schema file {}: {}", path.display(), err))?;
+    serde_json::from_str::<Value>(&content)
+        .map_err(|err| format!("Invalid JSON in schema file {}: {}", path.display(), err))?
+        .as_object()
+        .cloned()
+        .ok_or(format!("Schema file {} must contain a JSON object", path.display()).into())
+}
+
+/// Parses the final answer of the model, given in the output format, and asks the model again while
+/// it doesn't conform to the schema, `retries` times at most
+async fn get_structured_answer(
+    ollama_chat: &OllamaChat,
+    answer: String,
+    format: Value,
+    schema: Option<Map<String, Value>>,
+    retries: usize,
+) -> AppResult<Value> {
+    let schema_hint = match &schema {
+        Some(schema) => format!(
+            ", matching this JSON schema:\n{}",
+            Value::Object(schema.clone())
+        ),
+        None => String::new(),
+    };
+
+    let mut answer = answer;
+    let mut attempt = 0;
+
+    loop {
+        let errors = match (serde_json::from_str::<Value>(answer.trim()), &schema) {
+            (Ok(value), None) => return Ok(value),
+            (Ok(value), Some(schema)) => match validate_json(schema, value) {
+                Ok(value) => return Ok(value),
+                Err(errors) => format_validation_errors(&errors),
+            },
+            (Err(err), _) => format!("- invalid JSON: {}", err),
+        };
+
+        if attempt >= retries {
+            return Err(format!(
+                "The answer doesn't conform to the expected format after {} attempts:\n{}",
+                attempt + 1,
+                errors
+            )
+            .into());
+        }
+        attempt += 1;
+
+        eprintln!(
+            "The answer doesn't conform to the expected format, retrying ({}/{})",
+            attempt, retries
+        );
+        let instruction = format!(
+            "Your answer doesn't conform to the expected format:\n{}\nGive your answer again as JSON only{}",
+            errors, schema_hint
+        );
+        answer = ollama_chat.format_answer(&format, instruction).await?;
+    }
+}
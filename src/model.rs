@@ -1,13 +1,101 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use chrono::{DateTime, Utc};
 use ollama_rs::Ollama;
+use reqwest::Client;
 
 use crate::{
     AppResult,
+    ollama::{RunningModel, show_model_info},
     ui::{
         input::{self, MenuChoice},
         utils::{AnsiColor, colorize_text},
     },
 };
 
+/// Details of a model needed for every chat request
+#[derive(Debug, Clone)]
+pub struct ModelDetails {
+    pub capabilities: Vec<String>,
+    /// Maximum context length the model was trained with
+    pub context_length: Option<u64>,
+}
+
+impl ModelDetails {
+    pub fn has_capability(&self, capability: &str) -> bool {
+        self.capabilities.iter().any(|c| c == capability)
+    }
+}
+
+/// Model details fetched from Ollama, cached per model name until invalidated
+#[derive(Debug, Clone, Default)]
+pub struct ModelInfoCache {
+    entries: Arc<Mutex<HashMap<String, ModelDetails>>>,
+}
+
+impl ModelInfoCache {
+    pub async fn get(
+        &self,
+        ollama: &Ollama,
+        http_client: &Client,
+        model_name: &str,
+    ) -> AppResult<ModelDetails> {
+        if let Some(details) = self.entries.lock().unwrap().get(model_name) {
+            return Ok(details.clone());
+        }
+
+        let info = match show_model_info(ollama, http_client, model_name).await {
+            Ok(Some(info)) => info,
+            Err(err) if err.is::<reqwest::Error>() => {
+                return Err(format!("Unable to reach Ollama: {}", err).into());
+            }
+            Ok(None) => {
+                return Err(format!(
+                    "Model '{}' is not available in Ollama. Use /model pull to download it or /model select to choose another model",
+                    model_name
+                )
+                .into());
+            }
+            Err(err) => {
+                return Err(format!(
+                    "Unable to get information about model '{}': {}",
+                    model_name, err
+                )
+                .into());
+            }
+        };
+
+        let context_length = info
+            .model_info
+            .get("general.architecture")
+            .and_then(|a| a.as_str())
+            .and_then(|architecture| {
+                info.model_info
+                    .get(&format!("{}.context_length", architecture))
+            })
+            .and_then(|c| c.as_u64());
+
+        let details = ModelDetails {
+            capabilities: info.capabilities,
+            context_length,
+        };
+
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(model_name.to_string(), details.clone());
+
+        Ok(details)
+    }
+
+    pub fn invalidate(&self, model_name: &str) {
+        self.entries.lock().unwrap().remove(model_name);
+    }
+}
+
 pub async fn select_model(ollama: &Ollama) -> AppResult<String> {
     let models = ollama.list_local_models().await.unwrap_or_default();
 
@@ -82,3 +170,74 @@ pub async fn render_model_info(model_name: String, ollama: &Ollama) -> String {
 
     printed_info
 }
+
+pub fn render_running_models(models: &[RunningModel]) -> String {
+    if models.is_empty() {
+        return "No models loaded".to_string();
+    }
+
+    models
+        .iter()
+        .map(|model| {
+            let gpu_percent = model
+                .size_vram
+                .saturating_mul(100)
+                .checked_div(model.size)
+                .unwrap_or(0);
+
+            format!(
+                "{}\nSize: {}\nVRAM: {} ({}% GPU)\nExpires: {}\n",
+                colorize_text(&model.name, AnsiColor::BrightBlue),
+                format_bytes(model.size),
+                format_bytes(model.size_vram),
+                gpu_percent,
+                format_expiry(&model.expires_at)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n \n")
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1000.0 && unit < UNITS.len() - 1 {
+        size /= 1000.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+fn format_expiry(expires_at: &str) -> String {
+    let Ok(expires_at) = DateTime::parse_from_rfc3339(expires_at) else {
+        return expires_at.to_string();
+    };
+
+    let remaining = expires_at.with_timezone(&Utc) - Utc::now();
+    // Models kept loaded with a negative keep_alive expire centuries from now
+    if remaining.num_days() > 365 {
+        "Never".to_string()
+    } else if remaining.num_seconds() <= 0 {
+        "Now".to_string()
+    } else if remaining.num_hours() > 0 {
+        format!(
+            "In {}h {}m",
+            remaining.num_hours(),
+            remaining.num_minutes() % 60
+        )
+    } else if remaining.num_minutes() > 0 {
+        format!(
+            "In {}m {}s",
+            remaining.num_minutes(),
+            remaining.num_seconds() % 60
+        )
+    } else {
+        format!("In {}s", remaining.num_seconds())
+    }
+}
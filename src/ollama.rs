@@ -0,0 +1,285 @@
+use std::{
+    collections::VecDeque,
+    pin::Pin,
+    sync::{Arc, Mutex},
+};
+
+use futures::{Stream, stream};
+use ollama_rs::{
+    Ollama,
+    generation::chat::{ChatMessage, ChatMessageResponse, request::ChatMessageRequest},
+    models::ModelInfo,
+};
+use reqwest::{
+    Client, Response, StatusCode, Url,
+    header::{AUTHORIZATION, HeaderMap, HeaderName, HeaderValue},
+};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::{
+    AppResult,
+    settings::SettingsManager,
+    ui::utils::{AnsiColor, colorize_text},
+};
+
+pub type ChatStream = Pin<Box<dyn Stream<Item = ChatMessageResponse> + Send>>;
+
+/// Builds the Ollama client from the connection settings, with the HTTP client it uses.
+///
+/// Like `OLLAMA_HOST`, a host without a scheme uses http and port 11434. A path prefix is kept,
+/// for Ollama servers behind a reverse proxy.
+pub fn create_ollama_client(settings: &SettingsManager) -> AppResult<(Ollama, Client)> {
+    let host = settings.ollama_host.trim();
+    let (mut url, default_port) = if host.contains("://") {
+        (Url::parse(host)?, None)
+    } else {
+        (Url::parse(&format!("http://{}", host))?, Some(11434))
+    };
+
+    let port = url
+        .port()
+        .or(default_port)
+        .or(url.port_or_known_default())
+        .ok_or(format!(
+            "Unable to determine the port of Ollama host '{}'",
+            host
+        ))?;
+
+    // API paths are appended to the host URL
+    if !url.path().ends_with('/') {
+        let path = format!("{}/", url.path());
+        url.set_path(&path);
+    }
+
+    let mut headers = HeaderMap::new();
+    for (name, value) in &settings.ollama_headers {
+        headers.insert(
+            HeaderName::from_bytes(name.as_bytes())?,
+            HeaderValue::from_str(value)?,
+        );
+    }
+    if !settings.ollama_bearer_token.is_empty() {
+        headers.insert(
+            AUTHORIZATION,
+            HeaderValue::from_str(&format!("Bearer {}", settings.ollama_bearer_token))?,
+        );
+    }
+
+    let client = Client::builder().default_headers(headers).build()?;
+    Ok((Ollama::new_with_client(url, port, client.clone()), client))
+}
+
+/// Sends a chat request with the history, like `Ollama::send_chat_messages_with_history_stream`.
+///
+/// `extra_fields` are merged into the request body, for parameters ollama-rs doesn't support (objects
+/// such as `options` are merged key by key). When the answer is done, it is added to the history, with
+/// its thinking when `keep_thinking` is set.
+pub async fn send_chat_stream(
+    ollama: &Ollama,
+    http_client: &Client,
+    history: Arc<Mutex<Vec<ChatMessage>>>,
+    request: ChatMessageRequest,
+    extra_fields: &Map<String, Value>,
+    keep_thinking: bool,
+) -> AppResult<ChatStream> {
+    let mut request = request;
+    {
+        let mut history_guard = history.lock().unwrap();
+        history_guard.extend(request.messages.drain(..));
+        request.messages = history_guard.clone();
+    }
+
+    let mut body = serde_json::to_value(&request)?;
+    if let Value::Object(body) = &mut body {
+        body.insert("stream".to_string(), Value::Bool(true));
+        for (key, value) in extra_fields {
+            match (body.get_mut(key), value) {
+                (Some(Value::Object(existing)), Value::Object(extra)) => {
+                    existing.extend(extra.clone());
+                }
+                _ => {
+                    body.insert(key.clone(), value.clone());
+                }
+            }
+        }
+    }
+
+    let response = http_client
+        .post(format!("{}api/chat", ollama.url_str()))
+        .json(&body)
+        .send()
+        .await?;
+
+    if !response.status().is_success() {
+        let error = response.text().await?;
+        return Err(parse_error_message(&error).unwrap_or(error).into());
+    }
+
+    let state = ChatStreamState {
+        response,
+        buffer: Vec::new(),
+        pending: VecDeque::new(),
+        content: String::new(),
+        thinking: String::new(),
+        history,
+        keep_thinking,
+    };
+
+    Ok(Box::pin(stream::unfold(state, next_chat_response)))
+}
+
+struct ChatStreamState {
+    response: Response,
+    /// Received bytes not forming a complete line yet
+    buffer: Vec<u8>,
+    pending: VecDeque<ChatMessageResponse>,
+    content: String,
+    thinking: String,
+    history: Arc<Mutex<Vec<ChatMessage>>>,
+    keep_thinking: bool,
+}
+
+async fn next_chat_response(
+    mut state: ChatStreamState,
+) -> Option<(ChatMessageResponse, ChatStreamState)> {
+    loop {
+        if let Some(response) = state.pending.pop_front() {
+            state.content.push_str(&response.message.content);
+            if let Some(thinking) = &response.message.thinking {
+                state.thinking.push_str(thinking);
+            }
+
+            if response.done {
+                let mut message = ChatMessage::assistant(state.content.clone());
+                if state.keep_thinking && !state.thinking.is_empty() {
+                    message.thinking = Some(state.thinking.clone());
+                }
+                state.history.lock().unwrap().push(message);
+            }
+
+            return Some((response, state));
+        }
+
+        let chunk = match state.response.chunk().await {
+            Ok(Some(chunk)) => chunk,
+            Ok(None) => return None,
+            Err(err) => {
+                eprintln!("Error reading the Ollama response: {}", err);
+                return None;
+            }
+        };
+        state.buffer.extend_from_slice(&chunk);
+
+        while let Some(end) = state.buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = state.buffer.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            match serde_json::from_str::<ChatMessageResponse>(line) {
+                Ok(response) => state.pending.push_back(response),
+                Err(err) => {
+                    let error = parse_error_message(line).unwrap_or(err.to_string());
+                    eprintln!("{}", colorize_text(&error, AnsiColor::BrightRed));
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+/// A model loaded in memory, as listed by Ollama's running models API
+#[derive(Debug, Deserialize)]
+pub struct RunningModel {
+    pub name: String,
+    /// Memory used by the model, in bytes
+    pub size: u64,
+    /// Part of `size` loaded in GPU memory, in bytes
+    #[serde(default)]
+    pub size_vram: u64,
+    pub expires_at: String,
+}
+
+pub async fn list_running_models(
+    ollama: &Ollama,
+    http_client: &Client,
+) -> AppResult<Vec<RunningModel>> {
+    #[derive(Deserialize)]
+    struct RunningModels {
+        models: Vec<RunningModel>,
+    }
+
+    let response = http_client
+        .get(format!("{}api/ps", ollama.url_str()))
+        .send()
+        .await?;
+
+    if !response.status().is_success() {
+        let error = response.text().await?;
+        return Err(parse_error_message(&error).unwrap_or(error).into());
+    }
+
+    Ok(response.json::<RunningModels>().await?.models)
+}
+
+/// Loads a model in memory with an empty generate request, `keep_alive` being sent as is when set
+pub async fn load_model(
+    ollama: &Ollama,
+    http_client: &Client,
+    model_name: &str,
+    keep_alive: Option<Value>,
+) -> AppResult<()> {
+    let mut body = serde_json::json!({ "model": model_name });
+    if let Some(keep_alive) = keep_alive {
+        body["keep_alive"] = keep_alive;
+    }
+
+    let response = http_client
+        .post(format!("{}api/generate", ollama.url_str()))
+        .json(&body)
+        .send()
+        .await?;
+
+    if !response.status().is_success() {
+        let error = response.text().await?;
+        return Err(parse_error_message(&error).unwrap_or(error).into());
+    }
+
+    Ok(())
+}
+
+/// Information about a model from Ollama's show API, `None` when Ollama doesn't have the model
+pub async fn show_model_info(
+    ollama: &Ollama,
+    http_client: &Client,
+    model_name: &str,
+) -> AppResult<Option<ModelInfo>> {
+    let response = http_client
+        .post(format!("{}api/show", ollama.url_str()))
+        .json(&serde_json::json!({ "model": model_name }))
+        .send()
+        .await?;
+
+    if response.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    if !response.status().is_success() {
+        let error = response.text().await?;
+        return Err(parse_error_message(&error).unwrap_or(error).into());
+    }
+
+    let body = response.bytes().await?;
+    Ok(Some(serde_json::from_slice::<ModelInfo>(&body)?))
+}
+
+/// Extracts the message of an Ollama `{"error": "..."}` response
+fn parse_error_message(body: &str) -> Option<String> {
+    serde_json::from_str::<Value>(body)
+        .ok()?
+        .get("error")?
+        .as_str()
+        .map(String::from)
+}
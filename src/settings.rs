@@ -1,10 +1,12 @@
 use std::{
+    collections::HashMap,
     fs::{read_to_string, write},
     path::{Path, PathBuf},
 };
 
+use ollama_rs::models::ModelOptions;
 use serde::{Deserialize, Serialize};
-use serde_json::{Number, Value};
+use serde_json::{Map, Number, Value};
 
 use crate::{
     AppResult, ConfigFile, get_config_path,
@@ -15,22 +17,97 @@ use crate::{
     },
 };
 
-static CATEGORIES: [(&str, &[&str]); 3] = [
+static CATEGORIES: [(&str, &[&str]); 7] = [
     (
         "Model",
         &[
             "model_name",
             "show_thinking",
+            "think",
+            "keep_thinking_history",
             "model_seed",
             "model_temperature",
             "model_system_prompt",
+            "keep_alive",
         ],
     ),
-    ("Tool Calls", &["verbose_tool_calls", "tool_confirmation"]),
+    (
+        "Sampling",
+        &[
+            "num_ctx",
+            "top_p",
+            "top_k",
+            "min_p",
+            "repeat_penalty",
+            "num_predict",
+            "stop",
+            "mirostat",
+        ],
+    ),
+    ("Context", &["context_strategy", "context_threshold"]),
+    (
+        "Tool Calls",
+        &[
+            "verbose_tool_calls",
+            "tool_confirmation",
+            "always_allowed_tools",
+            "tool_result_max_length",
+            "max_parallel_tool_calls",
+            "max_tool_rounds",
+            "max_identical_tool_calls",
+            "tool_result_cache",
+            "tool_cache_ttl",
+            "audit_log",
+            "simplify_tool_schemas",
+            "tool_description_max_length",
+            "tool_call_fallback",
+        ],
+    ),
+    (
+        "Tool Retrieval",
+        &[
+            "tool_retrieval",
+            "tool_retrieval_model",
+            "tool_retrieval_top_k",
+            "pinned_tools",
+        ],
+    ),
+    (
+        "Ollama",
+        &["ollama_host", "ollama_headers", "ollama_bearer_token"],
+    ),
     ("Configuration", &["auto_save_config", "config_file_path"]),
 ];
 
-static OPTIONAL_VALUES: [&str; 1] = ["model_system_prompt"];
+static OPTIONAL_VALUES: [&str; 10] = [
+    "model_system_prompt",
+    "keep_alive",
+    "ollama_bearer_token",
+    "num_ctx",
+    "top_p",
+    "top_k",
+    "min_p",
+    "repeat_penalty",
+    "num_predict",
+    "mirostat",
+];
+
+/// Categories whose settings can be overridden per model
+static OVERRIDABLE_CATEGORIES: [&str; 3] = ["Model", "Sampling", "Context"];
+
+/// Allowed values of the settings holding an enum
+static ENUM_VALUES: [(&str, &[&str]); 2] = [
+    ("think", &["off", "on", "low", "medium", "high"]),
+    ("context_strategy", &["drop_oldest", "summarize", "warn"]),
+];
+
+fn overridable_keys() -> impl Iterator<Item = &'static str> {
+    CATEGORIES
+        .iter()
+        .filter(|(category, _)| OVERRIDABLE_CATEGORIES.contains(category))
+        .flat_map(|(_, keys)| keys.iter().copied())
+        .filter(|key| *key != "model_name")
+}
 
 fn format_settings_key(key: String) -> String {
     key.split('_')
@@ -57,21 +134,165 @@ fn format_settings_value(value: Value) -> String {
     }
 }
 
+/// How much the model thinks before answering, for thinking-capable models
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ThinkMode {
+    Off,
+    #[default]
+    On,
+    Low,
+    Medium,
+    High,
+}
+
+impl ThinkMode {
+    pub const ALL: [ThinkMode; 5] = [
+        ThinkMode::Off,
+        ThinkMode::On,
+        ThinkMode::Low,
+        ThinkMode::Medium,
+        ThinkMode::High,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ThinkMode::Off => "off",
+            ThinkMode::On => "on",
+            ThinkMode::Low => "low",
+            ThinkMode::Medium => "medium",
+            ThinkMode::High => "high",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ThinkMode> {
+        ThinkMode::ALL
+            .into_iter()
+            .find(|mode| mode.name() == name.to_lowercase())
+    }
+
+    /// Value of the `think` field of chat requests
+    pub fn to_request_value(self) -> Value {
+        match self {
+            ThinkMode::Off => Value::Bool(false),
+            ThinkMode::On => Value::Bool(true),
+            level => Value::String(level.name().to_string()),
+        }
+    }
+}
+
+/// What happens when the conversation gets close to the context window of the model
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContextStrategy {
+    /// Remove the oldest turns of the conversation
+    DropOldest,
+    /// Replace the oldest turns with a summary written by the model
+    Summarize,
+    /// Only print a warning
+    #[default]
+    Warn,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct SettingsManager {
     pub model_name: String,
     pub show_thinking: bool,
+    /// Thinking of thinking-capable models: off, on, or an effort level (low, medium, high)
+    pub think: ThinkMode,
+    /// Send the thinking of earlier answers back to the model
+    pub keep_thinking_history: bool,
     pub model_seed: i32,
     pub model_temperature: f32,
     pub model_system_prompt: String,
+    /// How long the model stays loaded after a request (e.g. 10m, 1h, 0 to unload it, -1 to keep it loaded)
+    pub keep_alive: String,
+    /// Context window size in tokens (Ollama defaults to a small context)
+    pub num_ctx: Option<u64>,
+    pub top_p: Option<f32>,
+    pub top_k: Option<u32>,
+    pub min_p: Option<f32>,
+    pub repeat_penalty: Option<f32>,
+    /// Maximum number of tokens generated per answer (-1 = unlimited)
+    pub num_predict: Option<i32>,
+    /// Sequences that stop the generation
+    pub stop: Vec<String>,
+    /// Mirostat sampling (0 = disabled, 1 = Mirostat, 2 = Mirostat 2.0)
+    pub mirostat: Option<u8>,
+    /// What to do when the conversation gets close to the context window: drop_oldest, summarize or warn
+    pub context_strategy: ContextStrategy,
+    /// Percentage of the context window the conversation may use before the context strategy applies
+    pub context_threshold: u64,
     pub verbose_tool_calls: bool,
     pub tool_confirmation: bool,
+    /// Tools that never ask for confirmation
+    pub always_allowed_tools: Vec<String>,
+    /// Maximum number of characters of a tool result sent to the model (0 = unlimited)
+    pub tool_result_max_length: usize,
+    /// Maximum number of tool calls run at the same time when no confirmation is needed
+    pub max_parallel_tool_calls: usize,
+    /// Maximum number of tool call rounds per user message (0 = unlimited)
+    pub max_tool_rounds: usize,
+    /// Maximum number of identical tool calls per user message (0 = unlimited)
+    pub max_identical_tool_calls: usize,
+    /// Reuse the results of identical calls to read-only or `cachedTools` tools
+    pub tool_result_cache: bool,
+    /// Number of seconds a cached tool result stays valid (0 = until the end of the session)
+    pub tool_cache_ttl: u64,
+    /// Record every tool call made by the model in the audit log
+    pub audit_log: bool,
+    /// Inline references, flatten unions and drop unsupported keywords in the tool schemas sent to the model
+    pub simplify_tool_schemas: bool,
+    /// Maximum length of tool and parameter descriptions in simplified schemas (0 = unlimited)
+    pub tool_description_max_length: usize,
+    /// Describe the tools in the system prompt for models without native tool calling
+    pub tool_call_fallback: bool,
+    /// Only send the tools most relevant to the conversation, selected with embeddings
+    pub tool_retrieval: bool,
+    /// Ollama embedding model used to select tools
+    pub tool_retrieval_model: String,
+    /// Number of tools sent to the model when tool retrieval is enabled
+    pub tool_retrieval_top_k: usize,
+    /// `server/tool` glob patterns of tools always sent to the model
+    pub pinned_tools: Vec<String>,
+    /// Ollama server URL, with an optional path prefix (e.g. https://example.com/ollama)
+    pub ollama_host: String,
+    /// Headers added to every request sent to Ollama
+    pub ollama_headers: HashMap<String, String>,
+    /// Token sent as `Authorization: Bearer <token>` to Ollama
+    pub ollama_bearer_token: String,
     pub auto_save_config: bool,
     pub config_file_path: PathBuf,
+    /// Named sets of `server/tool` glob patterns, applied with `/toolset use` or `--toolset`
+    pub toolsets: HashMap<String, Vec<String>>,
+    /// Model and Sampling settings overridden for a model, keyed by model name
+    pub model_overrides: HashMap<String, Map<String, Value>>,
 }
 
 impl SettingsManager {
     pub async fn render_edit_menu(&mut self) {
+        let scopes = vec![
+            MenuChoice {
+                name: "Global settings".to_string(),
+                shortcut: 'G',
+            },
+            MenuChoice {
+                name: format!("Overrides for {}", self.model_name),
+                shortcut: 'O',
+            },
+        ];
+
+        if menu_selection("Edit : ", scopes, true).await == 1 {
+            self.render_override_edit_menu().await;
+        } else {
+            self.render_global_edit_menu().await;
+        }
+
+        println!("Updated settings:\n{}", self.render(true));
+    }
+
+    async fn render_global_edit_menu(&mut self) {
         let json_value: Value =
             serde_json::to_value(self.clone()).expect("Failed to serialize settings");
 
@@ -99,53 +320,67 @@ impl SettingsManager {
 
         let current_value = json_value.get(&key).cloned().unwrap_or(Value::Null);
 
-        match current_value {
-            Value::String(_) => {
-                let new_value = text_input(&format!("New value for {}: ", key));
-                if !new_value.is_empty() || OPTIONAL_VALUES.contains(&key.as_str()) {
-                    self.update_setting(&key, Value::String(new_value));
-                }
-            }
-            Value::Number(_) => {
-                let new_value = text_input(&format!("New value for {}: ", key));
-                if !new_value.is_empty() {
-                    let new_value: Number = new_value.parse().unwrap_or(Number::from(0));
-                    self.update_setting(&key, Value::Number(new_value));
-                }
-            }
-            Value::Bool(_) => {
-                let choices = vec![
-                    MenuChoice {
-                        name: "Enabled".to_string(),
-                        shortcut: 'E',
-                    },
-                    MenuChoice {
-                        name: "Disabled".to_string(),
-                        shortcut: 'D',
-                    },
-                ];
-                let choice = menu_selection(
-                    &format!(
-                        "Toggle {} (current: {})",
-                        key,
-                        if current_value.as_bool().unwrap_or(false) {
-                            "Enabled"
-                        } else {
-                            "Disabled"
-                        }
-                    ),
-                    choices,
-                    true,
-                )
-                .await;
-                self.update_setting(&key, Value::Bool(choice == 0));
-            }
-            _ => {
-                println!("Unsupported setting type for {}", key);
+        if let Some(new_value) = prompt_setting_value(&key, &current_value).await {
+            self.update_setting(&key, new_value);
+        }
+    }
+
+    async fn render_override_edit_menu(&mut self) {
+        let json_value: Value = serde_json::to_value(self.with_model_overrides())
+            .expect("Failed to serialize settings");
+        let overrides = self
+            .model_overrides
+            .get(&self.model_name)
+            .cloned()
+            .unwrap_or_default();
+
+        let keys: Vec<&str> = overridable_keys().collect();
+        let choices = keys
+            .iter()
+            .map(|key| MenuChoice {
+                name: format!(
+                    "{}: {}{}",
+                    format_settings_key(key.to_string()),
+                    format_settings_value(json_value.get(*key).cloned().unwrap_or(Value::Null)),
+                    if overrides.contains_key(*key) {
+                        colorize_text(" (overridden)", AnsiColor::BrightBlack)
+                    } else {
+                        String::new()
+                    }
+                ),
+                shortcut: '#',
+            })
+            .collect();
+
+        let index = menu_selection(
+            &format!("Choose settings to override for {} : ", self.model_name),
+            choices,
+            true,
+        )
+        .await;
+        let key = keys[index as usize];
+
+        if overrides.contains_key(key) {
+            let actions = vec![
+                MenuChoice {
+                    name: "Change value".to_string(),
+                    shortcut: 'C',
+                },
+                MenuChoice {
+                    name: "Remove override".to_string(),
+                    shortcut: 'R',
+                },
+            ];
+            if menu_selection(&format!("{} is overridden", key), actions, true).await == 1 {
+                self.update_model_override(key, None);
+                return;
             }
         }
 
-        println!("Updated settings:\n{}", self.render(true));
+        let current_value = json_value.get(key).cloned().unwrap_or(Value::Null);
+        if let Some(new_value) = prompt_setting_value(key, &current_value).await {
+            self.update_model_override(key, Some(new_value));
+        }
     }
 
     fn update_setting(&mut self, key: &str, value: Value) {
@@ -165,6 +400,107 @@ impl SettingsManager {
         }
     }
 
+    /// Sets (or removes with `None`) the override of a setting for the current model
+    fn update_model_override(&mut self, key: &str, value: Option<Value>) {
+        let mut overrides = self
+            .model_overrides
+            .get(&self.model_name)
+            .cloned()
+            .unwrap_or_default();
+        match value {
+            Some(value) => {
+                overrides.insert(key.to_string(), value);
+            }
+            None => {
+                overrides.remove(key);
+            }
+        }
+
+        if let Err(err) = self.merge_overrides(&overrides) {
+            eprintln!("Invalid value for {}: {}", key, err);
+            return;
+        }
+
+        if overrides.is_empty() {
+            self.model_overrides.remove(&self.model_name);
+        } else {
+            self.model_overrides
+                .insert(self.model_name.clone(), overrides);
+        }
+
+        if self.auto_save_config {
+            self.save_to_file(&self.config_file_path)
+                .expect("Failed to save updated settings to config file");
+        }
+    }
+
+    /// Returns the settings with the overrides of the current model applied
+    pub fn with_model_overrides(&self) -> SettingsManager {
+        match self.model_overrides.get(&self.model_name) {
+            Some(overrides) => self
+                .merge_overrides(overrides)
+                .unwrap_or_else(|_| self.clone()),
+            None => self.clone(),
+        }
+    }
+
+    fn merge_overrides(&self, overrides: &Map<String, Value>) -> AppResult<SettingsManager> {
+        let mut json_value = serde_json::to_value(self)?;
+        if let Value::Object(map) = &mut json_value {
+            for key in overridable_keys() {
+                if let Some(value) = overrides.get(key) {
+                    map.insert(key.to_string(), value.clone());
+                }
+            }
+        }
+        Ok(serde_json::from_value(json_value)?)
+    }
+
+    /// Model options sent with chat requests. `min_p` isn't supported by ollama-rs and is added separately.
+    pub fn model_options(&self) -> ModelOptions {
+        let mut options = ModelOptions::default()
+            .seed(self.model_seed)
+            .temperature(self.model_temperature);
+
+        if let Some(num_ctx) = self.num_ctx {
+            options = options.num_ctx(num_ctx);
+        }
+        if let Some(top_p) = self.top_p {
+            options = options.top_p(top_p);
+        }
+        if let Some(top_k) = self.top_k {
+            options = options.top_k(top_k);
+        }
+        if let Some(repeat_penalty) = self.repeat_penalty {
+            options = options.repeat_penalty(repeat_penalty);
+        }
+        if let Some(num_predict) = self.num_predict {
+            options = options.num_predict(num_predict);
+        }
+        if !self.stop.is_empty() {
+            options = options.stop(self.stop.clone());
+        }
+        if let Some(mirostat) = self.mirostat {
+            options = options.mirostat(mirostat);
+        }
+
+        options
+    }
+
+    /// `keep_alive` as sent to Ollama, which parses it itself: a number of seconds, or a Go duration
+    /// string such as `1h30m`. It is not sent when empty, so that Ollama's default applies.
+    pub fn keep_alive(&self) -> Option<Value> {
+        let value = self.keep_alive.trim();
+        if value.is_empty() {
+            return None;
+        }
+
+        Some(match value.parse::<Number>() {
+            Ok(seconds) => Value::Number(seconds),
+            Err(_) => Value::String(value.to_string()),
+        })
+    }
+
     pub fn load_from_file(file_path: &Path) -> AppResult<SettingsManager> {
         let content = read_to_string(file_path)?;
         let settings: SettingsManager = serde_json::from_str(&content)?;
@@ -178,18 +514,144 @@ impl SettingsManager {
     }
 }
 
+/// Asks for a new value of a setting, depending on the type of its current value
+async fn prompt_setting_value(key: &str, current_value: &Value) -> Option<Value> {
+    let enum_values = ENUM_VALUES
+        .iter()
+        .find(|(name, _)| *name == key)
+        .map(|(_, values)| *values);
+
+    match (current_value, enum_values) {
+        (Value::String(current), Some(values)) => {
+            let choices = values
+                .iter()
+                .map(|value| MenuChoice {
+                    name: value.to_string(),
+                    shortcut: '#',
+                })
+                .collect();
+            let choice = menu_selection(
+                &format!("Choose {} (current: {})", key, current),
+                choices,
+                true,
+            )
+            .await;
+            Some(Value::String(values[choice as usize].to_string()))
+        }
+        (Value::String(_), None) => {
+            let new_value = text_input(&format!("New value for {}: ", key));
+            if !new_value.is_empty() || OPTIONAL_VALUES.contains(&key) {
+                Some(Value::String(new_value))
+            } else {
+                None
+            }
+        }
+        (Value::Number(_) | Value::Null, _) => {
+            let new_value = text_input(&format!("New value for {}: ", key));
+            if !new_value.is_empty() {
+                match new_value.parse::<Number>() {
+                    Ok(new_value) => Some(Value::Number(new_value)),
+                    Err(_) => {
+                        eprintln!("Invalid value for {}: {} is not a number", key, new_value);
+                        None
+                    }
+                }
+            } else if OPTIONAL_VALUES.contains(&key) {
+                Some(Value::Null)
+            } else {
+                None
+            }
+        }
+        (Value::Array(_), _) => {
+            let new_value = text_input(&format!("New values for {} (comma-separated): ", key));
+            Some(Value::Array(
+                new_value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|value| !value.is_empty())
+                    .map(|value| Value::String(value.to_string()))
+                    .collect(),
+            ))
+        }
+        (Value::Bool(_), _) => {
+            let choices = vec![
+                MenuChoice {
+                    name: "Enabled".to_string(),
+                    shortcut: 'E',
+                },
+                MenuChoice {
+                    name: "Disabled".to_string(),
+                    shortcut: 'D',
+                },
+            ];
+            let choice = menu_selection(
+                &format!(
+                    "Toggle {} (current: {})",
+                    key,
+                    if current_value.as_bool().unwrap_or(false) {
+                        "Enabled"
+                    } else {
+                        "Disabled"
+                    }
+                ),
+                choices,
+                true,
+            )
+            .await;
+            Some(Value::Bool(choice == 0))
+        }
+        _ => {
+            println!("Unsupported setting type for {}", key);
+            None
+        }
+    }
+}
+
 impl Default for SettingsManager {
     fn default() -> Self {
         Self {
             model_name: "qwen2.5:7b".to_string(),
             show_thinking: true,
+            think: ThinkMode::On,
+            keep_thinking_history: false,
             model_seed: 0,
             model_temperature: 0.8,
             model_system_prompt: String::new(),
+            keep_alive: String::new(),
+            num_ctx: None,
+            top_p: None,
+            top_k: None,
+            min_p: None,
+            repeat_penalty: None,
+            num_predict: None,
+            stop: Vec::new(),
+            mirostat: None,
+            context_strategy: ContextStrategy::Warn,
+            context_threshold: 80,
             tool_confirmation: true,
+            always_allowed_tools: Vec::new(),
+            tool_result_max_length: 0,
+            max_parallel_tool_calls: 4,
+            max_tool_rounds: 10,
+            max_identical_tool_calls: 2,
+            tool_result_cache: false,
+            tool_cache_ttl: 300,
+            audit_log: true,
+            simplify_tool_schemas: false,
+            tool_description_max_length: 300,
+            tool_call_fallback: false,
+            tool_retrieval: false,
+            tool_retrieval_model: "nomic-embed-text".to_string(),
+            tool_retrieval_top_k: 8,
+            pinned_tools: Vec::new(),
+            ollama_host: "http://localhost:11434".to_string(),
+            ollama_headers: HashMap::new(),
+            ollama_bearer_token: String::new(),
             config_file_path: get_config_path(ConfigFile::Settings),
             auto_save_config: true,
             verbose_tool_calls: true,
+            toolsets: HashMap::new(),
+            model_overrides: HashMap::new(),
         }
     }
 }
@@ -219,6 +681,21 @@ impl AppUIRenderable for SettingsManager {
                     formatted_content.push_str("\n \n");
                 }
             }
+
+            if let Some(overrides) = self.model_overrides.get(&self.model_name) {
+                formatted_content.push_str("\n \n");
+                formatted_content.push_str(&colorize_text(
+                    &format!("Overrides for {}\n", self.model_name),
+                    AnsiColor::BrightYellow,
+                ));
+                for (key, value) in overrides {
+                    formatted_content.push_str(&format!(
+                        "{}: {}\n",
+                        format_settings_key(key.to_string()),
+                        format_settings_value(value.clone())
+                    ));
+                }
+            }
         } else {
             return "Invalid settings format".to_string();
         };
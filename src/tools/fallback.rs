@@ -0,0 +1,128 @@
+use std::sync::LazyLock;
+
+use ollama_rs::generation::{
+    chat::{ChatMessage, MessageRole},
+    tools::{ToolCall, ToolCallFunction, ToolInfo},
+};
+use regex::Regex;
+use serde_json::Value;
+
+/// Start of the messages sending tool results back to the model
+const TOOL_RESULT_PREFIX: &str = "[Tool result: ";
+
+static FENCED_BLOCK_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?s)```[a-zA-Z_]*[ \t]*\r?\n(.*?)```").unwrap());
+static REACT_ACTION_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?m)^[ \t]*Action:[ \t]*`?([^\s`]+)`?[ \t]*\r?\n[ \t]*Action Input:[ \t]*")
+        .unwrap()
+});
+
+/// Describes the available tools and how to call them, for models without native tool calling
+pub fn render_tools_prompt(tools: &[ToolInfo]) -> String {
+    let tool_descriptions = tools
+        .iter()
+        .map(|tool| {
+            format!(
+                "- {}: {}\n  Parameters: {}",
+                tool.function.name,
+                tool.function.description,
+                serde_json::to_string(&tool.function.parameters).unwrap_or_default()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    format!(
+        "You can use tools to help answer the user. To call a tool, write a fenced code block in exactly this format, then stop and wait for its result:\n\n\
+        ```tool_call\n{{\"name\": \"tool_name\", \"arguments\": {{\"parameter\": \"value\"}}}}\n```\n\n\
+        Write one block per tool call. The results are sent back to you in a message starting with \"[Tool result\". \
+        Once you have the information you need, answer the user normally, without any tool_call block.\n\n\
+        Available tools:\n{}",
+        tool_descriptions
+    )
+}
+
+/// Extracts the tool calls written in a model answer, as fenced JSON blocks or in the ReAct format
+/// (`Action: <tool>` followed by `Action Input: <json>`).
+///
+/// Calls to tools that are not in `tools` are ignored, so that regular JSON in an answer is not taken for a call.
+pub fn parse_tool_calls(text: &str, tools: &[ToolInfo]) -> Vec<ToolCall> {
+    let is_known_tool = |name: &str| tools.iter().any(|t| t.function.name == name);
+    let mut calls = Vec::new();
+
+    for block in FENCED_BLOCK_REGEX.captures_iter(text) {
+        let values = match serde_json::from_str::<Value>(block[1].trim()) {
+            Ok(Value::Array(values)) => values,
+            Ok(value) => vec![value],
+            Err(_) => continue,
+        };
+
+        for value in values {
+            let name = value.get("name").and_then(|n| n.as_str()).unwrap_or("");
+            if is_known_tool(name) {
+                let arguments = value
+                    .get("arguments")
+                    .or_else(|| value.get("parameters"))
+                    .cloned()
+                    .unwrap_or(Value::Object(Default::default()));
+                calls.push(new_tool_call(name, arguments));
+            }
+        }
+    }
+
+    if calls.is_empty() {
+        for action in REACT_ACTION_REGEX.captures_iter(text) {
+            let name = &action[1];
+            let input_start = action.get(0).map(|m| m.end()).unwrap_or(text.len());
+            let arguments = serde_json::Deserializer::from_str(&text[input_start..])
+                .into_iter::<Value>()
+                .next()
+                .and_then(|value| value.ok());
+
+            if let Some(arguments) = arguments
+                && is_known_tool(name)
+            {
+                calls.push(new_tool_call(name, arguments));
+            }
+        }
+    }
+
+    calls
+}
+
+/// Builds the message sending the results of prompt-based tool calls back to the model
+pub fn render_tool_results(calls: &[ToolCall], results: &[ChatMessage]) -> ChatMessage {
+    ChatMessage::user(
+        calls
+            .iter()
+            .zip(results)
+            .map(|(call, result)| {
+                format!(
+                    "{}{}]\n{}",
+                    TOOL_RESULT_PREFIX, call.function.name, result.content
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n"),
+    )
+}
+
+/// Whether a message holds the results of prompt-based tool calls rather than a user message
+pub fn is_tool_results_message(message: &ChatMessage) -> bool {
+    message.role == MessageRole::User && message.content.starts_with(TOOL_RESULT_PREFIX)
+}
+
+fn new_tool_call(name: &str, arguments: Value) -> ToolCall {
+    // Some models write the arguments as a JSON string
+    let arguments = match arguments {
+        Value::String(s) => serde_json::from_str(&s).unwrap_or(Value::String(s)),
+        arguments => arguments,
+    };
+
+    ToolCall {
+        function: ToolCallFunction {
+            name: name.to_string(),
+            arguments,
+        },
+    }
+}
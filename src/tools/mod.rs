@@ -1,25 +1,54 @@
+pub mod fallback;
+pub mod policy;
+pub mod retrieval;
+pub mod schema;
 pub mod server;
 pub mod tool;
 
-use rmcp::model::{CallToolRequestParam, CallToolResult};
+use regex::Regex;
+use rmcp::{
+    RoleClient,
+    model::{CallToolRequestParam, CallToolResult},
+    service::Peer,
+};
 use serde_json::{Map, Value as JsonValue};
 use server::MCPServer;
 
 use crate::args::Args;
-use crate::tools::server::MCPServerConfig;
+use crate::tools::policy::{PolicyAction, ToolPolicy};
+use crate::tools::schema::canonical_json;
+use crate::tools::server::{MCPServerConfig, MCPServerOptions};
 use crate::{AppResult, tools::tool::MCPTool};
 use crate::{ConfigFile, get_config_path};
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+#[derive(Debug)]
+struct CachedResult {
+    /// Server of the tool, whose cached results are dropped when one of its other tools changes something
+    server: Option<String>,
+    result: CallToolResult,
+    cached_at: Instant,
+}
 
 #[derive(Debug)]
 pub struct ToolManager {
     services: Vec<MCPServer>,
+    policy: ToolPolicy,
+    /// Results of cacheable tool calls made during this session, keyed by tool and canonical arguments
+    result_cache: HashMap<String, CachedResult>,
 }
 
 impl ToolManager {
-    pub fn new(services: Vec<MCPServer>) -> Self {
-        ToolManager { services }
+    pub fn new(services: Vec<MCPServer>, policy: ToolPolicy) -> Self {
+        ToolManager {
+            services,
+            policy,
+            result_cache: HashMap::new(),
+        }
     }
 
     pub async fn initialize(&mut self) -> AppResult<()> {
@@ -37,11 +66,18 @@ impl ToolManager {
             .collect()
     }
 
+    pub fn get_tool(&self, name: &str) -> Option<MCPTool> {
+        self.get_tools()
+            .into_iter()
+            .find(|t| t.tool_info.name == name)
+    }
+
     pub fn set_tool_status(&mut self, name: &str, enabled: bool) -> AppResult<()> {
         for service in &mut self.services {
             for tool in &mut service.tools {
                 if tool.tool_info.name == name {
                     tool.enabled = enabled;
+                    tool.saved_enabled = enabled;
                     return Ok(());
                 }
             }
@@ -49,6 +85,102 @@ impl ToolManager {
         Err(format!("Tool '{}' not found.", name).into())
     }
 
+    /// Writes the saved enabled/disabled state of every tool back to the configuration file of its
+    /// server, leaving out the changes made by toolsets
+    pub fn save_tool_status(&mut self) -> AppResult<()> {
+        let mut config_files: HashMap<PathBuf, JsonValue> = HashMap::new();
+
+        for service in &mut self.services {
+            let config_path = match &service.options.config_path {
+                Some(config_path) => config_path.clone(),
+                None => continue,
+            };
+
+            let config_json = match config_files.entry(config_path.clone()) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    entry.insert(serde_json::from_str(&fs::read_to_string(&config_path)?)?)
+                }
+            };
+
+            let server_config = config_json
+                .get_mut("mcpServers")
+                .and_then(|v| v.get_mut(service.config.name()))
+                .and_then(|v| v.as_object_mut())
+                .ok_or(format!(
+                    "Server '{}' not found in {:?}",
+                    service.config.name(),
+                    config_path
+                ))?;
+
+            if service.options.enabled_tools.is_some() {
+                let enabled_tools: Vec<String> = service
+                    .tools
+                    .iter()
+                    .filter(|t| t.saved_enabled)
+                    .map(|t| t.tool_info.name.to_string())
+                    .collect();
+                server_config.insert("enabledTools".to_string(), enabled_tools.clone().into());
+                server_config.remove("disabledTools");
+                service.options.enabled_tools = Some(enabled_tools);
+                service.options.disabled_tools.clear();
+            } else {
+                // Keep tools that are not currently provided by the server, they may come back
+                let mut disabled_tools: Vec<String> = service
+                    .options
+                    .disabled_tools
+                    .iter()
+                    .filter(|name| !service.tools.iter().any(|t| t.tool_info.name == **name))
+                    .cloned()
+                    .collect();
+                disabled_tools.extend(
+                    service
+                        .tools
+                        .iter()
+                        .filter(|t| !t.saved_enabled)
+                        .map(|t| t.tool_info.name.to_string()),
+                );
+
+                if disabled_tools.is_empty() {
+                    server_config.remove("disabledTools");
+                } else {
+                    server_config
+                        .insert("disabledTools".to_string(), disabled_tools.clone().into());
+                }
+                service.options.disabled_tools = disabled_tools;
+            }
+        }
+
+        for (config_path, config_json) in config_files {
+            fs::write(config_path, serde_json::to_string_pretty(&config_json)?)?;
+        }
+
+        Ok(())
+    }
+
+    /// Enables the tools matching at least one of the patterns and disables every other tool, for the
+    /// session only.
+    ///
+    /// Patterns are `server/tool` globs, or tool globs matching on every server. Returns the number of enabled tools.
+    pub fn apply_toolset(&mut self, patterns: &[String]) -> usize {
+        let mut enabled_count = 0;
+
+        for service in &mut self.services {
+            let server_name = service.config.name().to_string();
+            for tool in &mut service.tools {
+                tool.enabled = patterns.iter().any(|pattern| {
+                    tool_pattern_match(pattern, Some(&server_name), &tool.tool_info.name)
+                });
+
+                if tool.enabled {
+                    enabled_count += 1;
+                }
+            }
+        }
+
+        enabled_count
+    }
+
     pub fn get_enabled_tools(&self) -> Vec<MCPTool> {
         self.services
             .iter()
@@ -58,28 +190,130 @@ impl ToolManager {
             .collect()
     }
 
+    pub fn get_result_limit(&self, name: &str) -> Option<usize> {
+        self.services
+            .iter()
+            .find(|s| s.tools.iter().any(|t| t.tool_info.name == name))
+            .and_then(|s| s.get_result_limit(name))
+    }
+
+    pub fn evaluate_policy(
+        &self,
+        name: &str,
+        arguments: &Map<String, JsonValue>,
+    ) -> Option<PolicyAction> {
+        self.policy
+            .evaluate(self.get_tool_server(name).as_deref(), name, arguments)
+    }
+
+    pub fn get_tool_server(&self, name: &str) -> Option<String> {
+        self.services
+            .iter()
+            .find(|s| s.tools.iter().any(|t| t.tool_info.name == name))
+            .map(|s| s.config.name().to_string())
+    }
+
+    /// Whether a tool matches a `server/tool` or tool glob pattern
+    pub fn tool_matches(&self, pattern: &str, name: &str) -> bool {
+        tool_pattern_match(pattern, self.get_tool_server(name).as_deref(), name)
+    }
+
+    pub fn is_tool_cacheable(&self, name: &str) -> bool {
+        self.services.iter().any(|s| {
+            s.tools
+                .iter()
+                .any(|t| t.tool_info.name == name && s.is_tool_cacheable(t))
+        })
+    }
+
+    /// Returns the cached result of an identical call, if it is younger than `ttl` (zero = no expiry)
+    pub fn get_cached_result(
+        &self,
+        name: &str,
+        arguments: &Map<String, JsonValue>,
+        ttl: Duration,
+    ) -> Option<CallToolResult> {
+        if !self.is_tool_cacheable(name) {
+            return None;
+        }
+
+        self.result_cache
+            .get(&result_cache_key(name, arguments))
+            .filter(|cached| ttl.is_zero() || cached.cached_at.elapsed() < ttl)
+            .map(|cached| cached.result.clone())
+    }
+
+    /// Stores the result of a successful call to a cacheable tool
+    pub fn cache_result(
+        &mut self,
+        name: &str,
+        arguments: &Map<String, JsonValue>,
+        result: &CallToolResult,
+    ) {
+        if !self.is_tool_cacheable(name) || result.is_error.unwrap_or(false) {
+            return;
+        }
+
+        self.result_cache.insert(
+            result_cache_key(name, arguments),
+            CachedResult {
+                server: self.get_tool_server(name),
+                result: result.clone(),
+                cached_at: Instant::now(),
+            },
+        );
+    }
+
+    /// Drops the cached results of the server of a tool that is not read-only after it ran, as the
+    /// call may have changed what the other tools of the server return
+    pub fn invalidate_cached_results(&mut self, name: &str) {
+        if self.get_tool(name).is_some_and(|tool| tool.is_read_only()) {
+            return;
+        }
+
+        let server = self.get_tool_server(name);
+        self.result_cache
+            .retain(|_, cached| cached.server != server);
+    }
+
+    /// Empties the result cache and returns the number of results removed
+    pub fn clear_result_cache(&mut self) -> usize {
+        let count = self.result_cache.len();
+        self.result_cache.clear();
+        count
+    }
+
+    /// Returns a handle to the client of the server providing a tool, usable without keeping the manager locked
+    pub fn get_tool_peer(&self, name: &str) -> Option<Peer<RoleClient>> {
+        self.services
+            .iter()
+            .find(|s| s.tools.iter().any(|t| t.tool_info.name == name))
+            .and_then(|s| s.client.as_ref())
+            .map(|client| client.peer().clone())
+    }
+
     pub async fn call_tool(
         &self,
         name: String,
         arguments: Map<String, JsonValue>,
     ) -> AppResult<CallToolResult> {
-        for service in &self.services {
-            for tool in &service.tools {
-                if tool.tool_info.name == name {
-                    if let Some(client) = &service.client {
-                        return Ok(client
-                            .call_tool(CallToolRequestParam {
-                                name: name.clone().into(),
-                                arguments: Some(arguments.clone()),
-                            })
-                            .await?);
-                    }
-                }
-            }
+        match self.get_tool_peer(&name) {
+            Some(peer) => ToolManager::call_tool_with_peer(peer, name, arguments).await,
+            None => Err(format!("Tool '{}' not found.", name).into()),
         }
+    }
 
-        let error_message = format!("Tool '{}' not found.", name);
-        Err(error_message.into())
+    pub async fn call_tool_with_peer(
+        peer: Peer<RoleClient>,
+        name: String,
+        arguments: Map<String, JsonValue>,
+    ) -> AppResult<CallToolResult> {
+        Ok(peer
+            .call_tool(CallToolRequestParam {
+                name: name.into(),
+                arguments: Some(arguments),
+            })
+            .await?)
     }
 
     pub fn load_mcp_servers_from_config(config_path: &Path) -> AppResult<Vec<MCPServer>> {
@@ -133,6 +367,45 @@ impl ToolManager {
                 .get("disabled")
                 .and_then(|v| v.as_bool())
                 .unwrap_or(false);
+            let max_result_length = server_config
+                .get("maxResultLength")
+                .and_then(|v| v.as_u64())
+                .map(|v| v as usize);
+            let tool_result_limits = server_config
+                .get("toolResultLimits")
+                .and_then(|v| v.as_object())
+                .map(|obj| {
+                    obj.iter()
+                        .filter_map(|(k, v)| v.as_u64().map(|limit| (k.clone(), limit as usize)))
+                        .collect()
+                })
+                .unwrap_or_default();
+            let enabled_tools = server_config
+                .get("enabledTools")
+                .and_then(|v| v.as_array())
+                .map(|arr| {
+                    arr.iter()
+                        .filter_map(|v| v.as_str().map(String::from))
+                        .collect::<Vec<_>>()
+                });
+            let disabled_tools = server_config
+                .get("disabledTools")
+                .and_then(|v| v.as_array())
+                .map(|arr| {
+                    arr.iter()
+                        .filter_map(|v| v.as_str().map(String::from))
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default();
+            let cached_tools = server_config
+                .get("cachedTools")
+                .and_then(|v| v.as_array())
+                .map(|arr| {
+                    arr.iter()
+                        .filter_map(|v| v.as_str().map(String::from))
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default();
 
             // Auto-detect type if not provided
             let detected_type = match (server_type, command.clone(), url.clone()) {
@@ -216,12 +489,36 @@ impl ToolManager {
             };
 
             // Create MCPServer
-            servers.push(MCPServer::new(server_config));
+            servers.push(MCPServer::new(
+                server_config,
+                MCPServerOptions {
+                    max_result_length,
+                    tool_result_limits,
+                    enabled_tools,
+                    disabled_tools,
+                    cached_tools,
+                    config_path: Some(config_path.to_path_buf()),
+                },
+            ));
         }
 
         Ok(servers)
     }
 
+    pub fn load_policy_from_args(args: &Args) -> AppResult<ToolPolicy> {
+        match &args.policy {
+            Some(policy_path) => ToolPolicy::load_from_file(Path::new(policy_path)),
+            None => {
+                let policy_path = get_config_path(ConfigFile::ToolPolicy);
+                if policy_path.exists() {
+                    ToolPolicy::load_from_file(&policy_path)
+                } else {
+                    Ok(ToolPolicy::default())
+                }
+            }
+        }
+    }
+
     pub fn load_mcp_server_from_args(args: Args) -> AppResult<Vec<MCPServer>> {
         let mut services = Vec::new();
 
@@ -248,43 +545,88 @@ impl ToolManager {
                 .rsplit_once('.')
                 .unwrap_or((stdio_server.as_str(), ""));
 
-            let server = MCPServer::new(MCPServerConfig::Stdio {
-                name: file_path.to_string(),
-                command: if ext == "js" {
-                    "node".to_string()
-                } else if ext == "py" {
-                    "python3".to_string()
-                } else {
-                    eprintln!("Unsupported file extension for stdio server: {}", ext);
-                    continue;
+            let server = MCPServer::new(
+                MCPServerConfig::Stdio {
+                    name: file_path.to_string(),
+                    command: if ext == "js" {
+                        "node".to_string()
+                    } else if ext == "py" {
+                        "python3".to_string()
+                    } else {
+                        eprintln!("Unsupported file extension for stdio server: {}", ext);
+                        continue;
+                    },
+                    args: vec![file_path.to_string()].into(),
+                    env: None,
+                    disabled: false,
                 },
-                args: vec![file_path.to_string()].into(),
-                env: None,
-                disabled: false,
-            });
+                MCPServerOptions::default(),
+            );
             services.push(server);
         }
 
         for sse_server in args.sse_server {
-            let server = MCPServer::new(MCPServerConfig::SSE {
-                name: sse_server.clone(),
-                url: sse_server,
-                headers: None,
-                disabled: false,
-            });
+            let server = MCPServer::new(
+                MCPServerConfig::SSE {
+                    name: sse_server.clone(),
+                    url: sse_server,
+                    headers: None,
+                    disabled: false,
+                },
+                MCPServerOptions::default(),
+            );
             services.push(server);
         }
 
         for http_server in args.streamable_http_server {
-            let server = MCPServer::new(MCPServerConfig::StreamableHttp {
-                name: http_server.clone(),
-                url: http_server,
-                headers: None,
-                disabled: false,
-            });
+            let server = MCPServer::new(
+                MCPServerConfig::StreamableHttp {
+                    name: http_server.clone(),
+                    url: http_server,
+                    headers: None,
+                    disabled: false,
+                },
+                MCPServerOptions::default(),
+            );
             services.push(server);
         }
 
         Ok(services)
     }
 }
+
+fn tool_pattern_match(pattern: &str, server: Option<&str>, tool: &str) -> bool {
+    match pattern.split_once('/') {
+        Some((server_pattern, tool_pattern)) => {
+            server.is_some_and(|server| glob_match(server_pattern, server))
+                && glob_match(tool_pattern, tool)
+        }
+        None => glob_match(pattern, tool),
+    }
+}
+
+fn result_cache_key(name: &str, arguments: &Map<String, JsonValue>) -> String {
+    format!(
+        "{}:{}",
+        name,
+        canonical_json(&JsonValue::Object(arguments.clone()))
+    )
+}
+
+/// Matches text against a glob pattern supporting `*` (any characters) and `?` (one character)
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let regex_pattern = pattern
+        .split('*')
+        .map(|part| {
+            part.split('?')
+                .map(regex::escape)
+                .collect::<Vec<_>>()
+                .join(".")
+        })
+        .collect::<Vec<_>>()
+        .join(".*");
+
+    Regex::new(&format!("^{}$", regex_pattern))
+        .map(|re| re.is_match(text))
+        .unwrap_or(false)
+}
@@ -0,0 +1,182 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::{
+    AppResult,
+    tools::{glob_match, schema::canonical_json},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PolicyAction {
+    Allow,
+    Deny,
+    Ask,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PolicyRuleConfig {
+    server: Option<String>,
+    tool: Option<String>,
+    #[serde(default)]
+    arguments: HashMap<String, String>,
+    arguments_regex: Option<String>,
+    action: PolicyAction,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ToolPolicyConfig {
+    default: Option<PolicyAction>,
+    #[serde(default)]
+    rules: Vec<PolicyRuleConfig>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PolicyRule {
+    pub server: Option<String>,
+    pub tool: Option<String>,
+    /// JSONPath expressions with the regex the value they select must match
+    pub arguments: Vec<(String, Regex)>,
+    /// Regex matched against the whole arguments JSON
+    pub arguments_regex: Option<Regex>,
+    pub action: PolicyAction,
+}
+
+/// Rules deciding whether a tool call is allowed, denied or needs confirmation.
+///
+/// Rules are evaluated in order and the first matching rule wins. When no rule matches,
+/// the `default` action applies, or the `tool_confirmation` setting when there is none.
+#[derive(Debug, Clone, Default)]
+pub struct ToolPolicy {
+    pub default: Option<PolicyAction>,
+    pub rules: Vec<PolicyRule>,
+}
+
+impl ToolPolicy {
+    pub fn load_from_file(file_path: &Path) -> AppResult<ToolPolicy> {
+        let content = fs::read_to_string(file_path)?;
+        let config: ToolPolicyConfig = serde_json::from_str(&content)?;
+
+        let mut rules = Vec::new();
+        for rule in config.rules {
+            let mut arguments = Vec::new();
+            for (path, pattern) in rule.arguments {
+                arguments.push((path, Regex::new(&pattern)?));
+            }
+
+            rules.push(PolicyRule {
+                server: rule.server,
+                tool: rule.tool,
+                arguments,
+                arguments_regex: rule
+                    .arguments_regex
+                    .map(|pattern| Regex::new(&pattern))
+                    .transpose()?,
+                action: rule.action,
+            });
+        }
+
+        Ok(ToolPolicy {
+            default: config.default,
+            rules,
+        })
+    }
+
+    pub fn evaluate(
+        &self,
+        server: Option<&str>,
+        tool: &str,
+        arguments: &Map<String, Value>,
+    ) -> Option<PolicyAction> {
+        let arguments = Value::Object(arguments.clone());
+
+        self.rules
+            .iter()
+            .find(|rule| rule.matches(server, tool, &arguments))
+            .map(|rule| rule.action)
+            .or(self.default)
+    }
+}
+
+impl PolicyRule {
+    fn matches(&self, server: Option<&str>, tool: &str, arguments: &Value) -> bool {
+        if let Some(server_pattern) = &self.server {
+            match server {
+                Some(server) if glob_match(server_pattern, server) => {}
+                _ => return false,
+            }
+        }
+
+        if let Some(tool_pattern) = &self.tool
+            && !glob_match(tool_pattern, tool)
+        {
+            return false;
+        }
+
+        if let Some(arguments_regex) = &self.arguments_regex
+            && !arguments_regex.is_match(&canonical_json(arguments))
+        {
+            return false;
+        }
+
+        self.arguments.iter().all(|(path, regex)| {
+            select_json_path(arguments, path)
+                .iter()
+                .any(|value| match value {
+                    Value::String(s) => regex.is_match(s),
+                    value => regex.is_match(&value.to_string()),
+                })
+        })
+    }
+}
+
+/// Selects the values matching a simple JSONPath expression (`$.a.b`, `$.a[0]`, `$.a[*]`, `$['a']`).
+///
+/// A path without the leading `$` is relative to the root.
+fn select_json_path<'a>(root: &'a Value, path: &str) -> Vec<&'a Value> {
+    let path = path.strip_prefix('$').unwrap_or(path);
+    let mut current = vec![root];
+
+    let mut segments = Vec::new();
+    let mut rest = path;
+    while !rest.is_empty() {
+        if let Some(stripped) = rest.strip_prefix('.') {
+            rest = stripped;
+            continue;
+        }
+
+        if let Some(stripped) = rest.strip_prefix('[') {
+            let end = stripped.find(']').unwrap_or(stripped.len());
+            segments.push(stripped[..end].trim_matches(|c| c == '\'' || c == '"'));
+            rest = stripped.get(end + 1..).unwrap_or("");
+        } else {
+            let end = rest.find(['.', '[']).unwrap_or(rest.len());
+            segments.push(&rest[..end]);
+            rest = &rest[end..];
+        }
+    }
+
+    for segment in segments {
+        current = current
+            .into_iter()
+            .flat_map(|value| match (segment, value) {
+                ("*", Value::Array(items)) => items.iter().collect(),
+                ("*", Value::Object(object)) => object.values().collect(),
+                (segment, Value::Array(items)) => segment
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|i| items.get(i))
+                    .into_iter()
+                    .collect(),
+                (segment, Value::Object(object)) => object.get(segment).into_iter().collect(),
+                _ => Vec::new(),
+            })
+            .collect();
+    }
+
+    current
+}
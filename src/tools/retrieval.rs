@@ -0,0 +1,140 @@
+use std::{cmp::Ordering, collections::HashMap, fs, path::PathBuf};
+
+use ollama_rs::{Ollama, generation::embeddings::request::GenerateEmbeddingsRequest};
+use serde::{Deserialize, Serialize};
+
+use crate::{AppResult, tools::tool::MCPTool};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedEmbedding {
+    /// Text the embedding was computed from, to detect tools whose description changed
+    text: String,
+    embedding: Vec<f32>,
+}
+
+/// Selects the tools most relevant to a conversation by comparing embeddings of their descriptions
+#[derive(Debug)]
+pub struct ToolRetriever {
+    cache_path: PathBuf,
+    /// Embeddings of tool descriptions, keyed by `model:tool`
+    embeddings: HashMap<String, CachedEmbedding>,
+}
+
+impl ToolRetriever {
+    pub fn load(cache_path: PathBuf) -> Self {
+        let embeddings = fs::read_to_string(&cache_path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+
+        ToolRetriever {
+            cache_path,
+            embeddings,
+        }
+    }
+
+    /// Returns the names of the `top_k` tools whose description is the closest to the query.
+    ///
+    /// Tool embeddings are only computed for tools missing from the cache, in a single request with the query.
+    pub async fn rank_tools(
+        &mut self,
+        ollama: &Ollama,
+        model: &str,
+        tools: &[MCPTool],
+        query: &str,
+        top_k: usize,
+    ) -> AppResult<Vec<String>> {
+        let entries: Vec<(String, String, String)> = tools
+            .iter()
+            .map(|tool| {
+                (
+                    tool.tool_info.name.to_string(),
+                    format!("{}:{}", model, tool.tool_info.name),
+                    embedding_text(tool),
+                )
+            })
+            .collect();
+
+        let missing: Vec<&(String, String, String)> = entries
+            .iter()
+            .filter(|(_, key, text)| {
+                self.embeddings
+                    .get(key)
+                    .is_none_or(|cached| cached.text != *text)
+            })
+            .collect();
+
+        let mut inputs: Vec<String> = missing.iter().map(|(_, _, text)| text.clone()).collect();
+        inputs.push(query.to_string());
+
+        let mut embeddings = ollama
+            .generate_embeddings(GenerateEmbeddingsRequest::new(
+                model.to_string(),
+                inputs.into(),
+            ))
+            .await?
+            .embeddings;
+
+        let query_embedding = embeddings
+            .pop()
+            .ok_or("The embedding model returned no embeddings")?;
+
+        if embeddings.len() != missing.len() {
+            return Err("The embedding model returned an unexpected number of embeddings".into());
+        }
+
+        if !missing.is_empty() {
+            for ((_, key, text), embedding) in missing.into_iter().zip(embeddings) {
+                self.embeddings.insert(
+                    key.clone(),
+                    CachedEmbedding {
+                        text: text.clone(),
+                        embedding,
+                    },
+                );
+            }
+            self.save()?;
+        }
+
+        let mut scores: Vec<(f32, String)> = entries
+            .into_iter()
+            .filter_map(|(name, key, _)| {
+                self.embeddings
+                    .get(&key)
+                    .map(|cached| (cosine_similarity(&cached.embedding, &query_embedding), name))
+            })
+            .collect();
+        scores.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(Ordering::Equal));
+
+        Ok(scores
+            .into_iter()
+            .take(top_k)
+            .map(|(_, name)| name)
+            .collect())
+    }
+
+    fn save(&self) -> AppResult<()> {
+        fs::write(&self.cache_path, serde_json::to_string(&self.embeddings)?)?;
+        Ok(())
+    }
+}
+
+fn embedding_text(tool: &MCPTool) -> String {
+    format!(
+        "{}: {}",
+        tool.tool_info.name,
+        tool.tool_info.description.as_deref().unwrap_or_default()
+    )
+}
+
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
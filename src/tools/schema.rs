@@ -0,0 +1,722 @@
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ValidationError {
+    pub path: String,
+    pub message: String,
+}
+
+/// Validates tool call arguments against a tool input schema.
+///
+/// Safe mismatches (e.g. `"5"` for an integer field) are coerced. Returns the possibly coerced
+/// arguments, or every validation error found.
+pub fn validate_arguments(
+    schema: &Map<String, Value>,
+    arguments: Map<String, Value>,
+) -> Result<Map<String, Value>, Vec<ValidationError>> {
+    match validate_json(schema, Value::Object(arguments))? {
+        Value::Object(arguments) => Ok(arguments),
+        _ => Ok(Map::new()),
+    }
+}
+
+/// Validates any JSON value against a schema, with the same coercions as `validate_arguments`
+pub fn validate_json(
+    schema: &Map<String, Value>,
+    value: Value,
+) -> Result<Value, Vec<ValidationError>> {
+    let mut errors = Vec::new();
+    let value = validate_value(schema, value, "", &mut errors);
+
+    if errors.is_empty() {
+        Ok(value)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Formats validation errors as a list for the user
+pub fn format_validation_errors(errors: &[ValidationError]) -> String {
+    errors
+        .iter()
+        .map(|e| format!("- {}: {}", e.path, e.message))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Builds the tool message content sent back to the model when its arguments are invalid
+pub fn render_validation_errors(tool_name: &str, errors: &[ValidationError]) -> String {
+    serde_json::json!({
+        "error": format!("Invalid arguments for tool '{}'", tool_name),
+        "validation_errors": errors,
+        "hint": "Fix the arguments according to the tool parameters schema and call the tool again",
+    })
+    .to_string()
+}
+
+fn validate_value(
+    schema: &Map<String, Value>,
+    value: Value,
+    path: &str,
+    errors: &mut Vec<ValidationError>,
+) -> Value {
+    let expected_types: Vec<&str> = match schema.get("type") {
+        Some(Value::String(t)) => vec![t.as_str()],
+        Some(Value::Array(types)) => types.iter().filter_map(|t| t.as_str()).collect(),
+        _ => vec![],
+    };
+
+    let value =
+        if expected_types.is_empty() || expected_types.iter().any(|t| matches_type(t, &value)) {
+            value
+        } else {
+            match expected_types.iter().find_map(|t| coerce_value(t, &value)) {
+                Some(coerced) => coerced,
+                None => {
+                    errors.push(ValidationError {
+                        path: display_path(path),
+                        message: format!(
+                            "expected {}, got {} {}",
+                            expected_types.join(" or "),
+                            type_name(&value),
+                            value
+                        ),
+                    });
+                    return value;
+                }
+            }
+        };
+
+    if let Some(Value::Array(allowed)) = schema.get("enum")
+        && !allowed.contains(&value)
+    {
+        errors.push(ValidationError {
+            path: display_path(path),
+            message: format!(
+                "{} is not one of the allowed values: {}",
+                value,
+                allowed
+                    .iter()
+                    .map(|v| v.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        });
+    }
+
+    match value {
+        Value::Object(object) => Value::Object(validate_object(schema, object, path, errors)),
+        Value::Array(items) => match schema.get("items").and_then(|i| i.as_object()) {
+            Some(items_schema) => Value::Array(
+                items
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, item)| {
+                        validate_value(items_schema, item, &format!("{}[{}]", path, i), errors)
+                    })
+                    .collect(),
+            ),
+            None => Value::Array(items),
+        },
+        value => value,
+    }
+}
+
+fn validate_object(
+    schema: &Map<String, Value>,
+    object: Map<String, Value>,
+    path: &str,
+    errors: &mut Vec<ValidationError>,
+) -> Map<String, Value> {
+    let properties = schema.get("properties").and_then(|p| p.as_object());
+    let required: Vec<&str> = match schema.get("required") {
+        Some(Value::Array(required)) => required.iter().filter_map(|r| r.as_str()).collect(),
+        _ => Vec::new(),
+    };
+
+    for name in &required {
+        if !object.contains_key(*name) {
+            errors.push(ValidationError {
+                path: join_path(path, name),
+                message: "required property is missing".to_string(),
+            });
+        }
+    }
+
+    let additional_properties_allowed =
+        !matches!(schema.get("additionalProperties"), Some(Value::Bool(false)));
+
+    object
+        .into_iter()
+        .filter_map(|(name, value)| {
+            let property_path = join_path(path, &name);
+            let value = match properties
+                .and_then(|p| p.get(&name))
+                .and_then(|p| p.as_object())
+            {
+                // Models often send null for optional parameters they don't use
+                Some(property_schema)
+                    if value.is_null()
+                        && !required.contains(&name.as_str())
+                        && !allows_null(property_schema) =>
+                {
+                    return None;
+                }
+                Some(property_schema) => {
+                    validate_value(property_schema, value, &property_path, errors)
+                }
+                None => {
+                    if !additional_properties_allowed {
+                        errors.push(ValidationError {
+                            path: property_path,
+                            message: "unknown property".to_string(),
+                        });
+                    }
+                    value
+                }
+            };
+            Some((name, value))
+        })
+        .collect()
+}
+
+/// Whether a schema accepts `null`, which it does when it doesn't restrict the type
+fn allows_null(schema: &Map<String, Value>) -> bool {
+    match schema.get("type") {
+        Some(Value::String(t)) => t == "null",
+        Some(Value::Array(types)) => types.iter().any(|t| t == "null"),
+        _ => true,
+    }
+}
+
+fn matches_type(expected: &str, value: &Value) -> bool {
+    match expected {
+        "string" => value.is_string(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        "boolean" => value.is_boolean(),
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        "null" => value.is_null(),
+        _ => true,
+    }
+}
+
+/// Converts a value to the expected type when it can be done without losing information
+fn coerce_value(expected: &str, value: &Value) -> Option<Value> {
+    match (expected, value) {
+        ("integer", Value::String(s)) => s.trim().parse::<i64>().ok().map(Value::from),
+        ("integer", Value::Number(n)) => n
+            .as_f64()
+            .filter(|f| f.fract() == 0.0)
+            .map(|f| Value::from(f as i64)),
+        ("number", Value::String(s)) => s.trim().parse::<f64>().ok().map(Value::from),
+        ("boolean", Value::String(s)) => match s.trim().to_lowercase().as_str() {
+            "true" => Some(Value::Bool(true)),
+            "false" => Some(Value::Bool(false)),
+            _ => None,
+        },
+        ("string", Value::Number(n)) => Some(Value::String(n.to_string())),
+        ("string", Value::Bool(b)) => Some(Value::String(b.to_string())),
+        ("array", Value::String(s)) => serde_json::from_str::<Value>(s)
+            .ok()
+            .filter(|v| v.is_array()),
+        ("object", Value::String(s)) => serde_json::from_str::<Value>(s)
+            .ok()
+            .filter(|v| v.is_object()),
+        _ => None,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn join_path(path: &str, name: &str) -> String {
+    if path.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", path, name)
+    }
+}
+
+fn display_path(path: &str) -> String {
+    if path.is_empty() {
+        "(root)".to_string()
+    } else {
+        path.to_string()
+    }
+}
+
+/// Serializes a JSON value with object keys sorted, so that equivalent values give the same string
+pub fn canonical_json(value: &Value) -> String {
+    fn sort_keys(value: &Value) -> Value {
+        match value {
+            Value::Object(object) => {
+                let mut entries: Vec<_> = object.iter().collect();
+                entries.sort_by(|a, b| a.0.cmp(b.0));
+                Value::Object(
+                    entries
+                        .into_iter()
+                        .map(|(k, v)| (k.clone(), sort_keys(v)))
+                        .collect(),
+                )
+            }
+            Value::Array(items) => Value::Array(items.iter().map(sort_keys).collect()),
+            value => value.clone(),
+        }
+    }
+
+    sort_keys(value).to_string()
+}
+
+/// Keywords removed from simplified schemas, as local models ignore them or get confused by them
+const UNSUPPORTED_KEYWORDS: [&str; 17] = [
+    "$schema",
+    "$id",
+    "$comment",
+    "$anchor",
+    "title",
+    "examples",
+    "readOnly",
+    "writeOnly",
+    "deprecated",
+    "contentEncoding",
+    "contentMediaType",
+    "if",
+    "then",
+    "else",
+    "not",
+    "patternProperties",
+    "unevaluatedProperties",
+];
+/// Enums with more values are turned into a description listing the first ones
+const MAX_ENUM_VALUES: usize = 25;
+/// Nested schemas deeper than this are reduced to their type and description
+const MAX_SCHEMA_DEPTH: usize = 6;
+
+/// Rewrites a tool input schema into a form local models handle better.
+///
+/// References are inlined, unions are flattened when no information is lost, unsupported keywords
+/// are removed and descriptions are shortened to `description_max_length` characters (0 = unlimited).
+/// Arguments are still validated against the original schema.
+pub fn simplify_schema(
+    schema: &Map<String, Value>,
+    description_max_length: usize,
+) -> Map<String, Value> {
+    let root = Value::Object(schema.clone());
+    match simplify_value(&root, &root, &mut Vec::new(), 0, description_max_length) {
+        Value::Object(mut simplified) => {
+            simplified.remove("$defs");
+            simplified.remove("definitions");
+            simplified
+        }
+        _ => schema.clone(),
+    }
+}
+
+/// Cuts a description down to `max_length` characters (0 = unlimited), preferably at the end of a sentence
+pub fn shorten_description(description: &str, max_length: usize) -> String {
+    let description = description.trim();
+    if max_length == 0 || description.chars().count() <= max_length {
+        return description.to_string();
+    }
+
+    let truncated: String = description.chars().take(max_length).collect();
+    match truncated.rfind(". ") {
+        Some(end) if end > max_length / 2 => truncated[..=end].to_string(),
+        _ => format!("{}...", truncated.trim_end()),
+    }
+}
+
+fn simplify_value(
+    value: &Value,
+    root: &Value,
+    ref_stack: &mut Vec<String>,
+    depth: usize,
+    description_max_length: usize,
+) -> Value {
+    let Value::Object(schema) = value else {
+        return value.clone();
+    };
+
+    // Inline references, keeping the siblings of `$ref`
+    if let Some(Value::String(reference)) = schema.get("$ref") {
+        if ref_stack.contains(reference) {
+            // Recursive schema, stop expanding it
+            let mut schema = schema.clone();
+            schema.remove("$ref");
+            schema
+                .entry("type")
+                .or_insert_with(|| Value::String("object".to_string()));
+            return simplify_value(
+                &Value::Object(schema),
+                root,
+                ref_stack,
+                depth,
+                description_max_length,
+            );
+        }
+
+        if let Some(Value::Object(target)) = resolve_reference(root, reference) {
+            let mut merged = target.clone();
+            for (key, value) in schema.iter().filter(|(key, _)| *key != "$ref") {
+                merged.insert(key.clone(), value.clone());
+            }
+
+            ref_stack.push(reference.clone());
+            let simplified = simplify_value(
+                &Value::Object(merged),
+                root,
+                ref_stack,
+                depth,
+                description_max_length,
+            );
+            ref_stack.pop();
+            return simplified;
+        }
+    }
+
+    let mut simplified = Map::new();
+    for (key, value) in schema {
+        if UNSUPPORTED_KEYWORDS.contains(&key.as_str()) || key == "$ref" {
+            continue;
+        }
+
+        let value = match key.as_str() {
+            "properties" => match value {
+                Value::Object(properties) => Value::Object(
+                    properties
+                        .iter()
+                        .map(|(name, property)| {
+                            (
+                                name.clone(),
+                                simplify_value(
+                                    property,
+                                    root,
+                                    ref_stack,
+                                    depth + 1,
+                                    description_max_length,
+                                ),
+                            )
+                        })
+                        .collect(),
+                ),
+                value => value.clone(),
+            },
+            "items" | "additionalProperties" if value.is_object() => {
+                simplify_value(value, root, ref_stack, depth + 1, description_max_length)
+            }
+            "anyOf" | "oneOf" | "allOf" => match value {
+                Value::Array(variants) => Value::Array(
+                    variants
+                        .iter()
+                        .map(|variant| {
+                            simplify_value(variant, root, ref_stack, depth, description_max_length)
+                        })
+                        .collect(),
+                ),
+                value => value.clone(),
+            },
+            "description" => match value {
+                Value::String(description) => {
+                    Value::String(shorten_description(description, description_max_length))
+                }
+                value => value.clone(),
+            },
+            _ => value.clone(),
+        };
+        simplified.insert(key.clone(), value);
+    }
+
+    flatten_unions(&mut simplified);
+    reduce_large_enum(&mut simplified);
+
+    if depth >= MAX_SCHEMA_DEPTH {
+        simplified.retain(|key, _| key == "type" || key == "description");
+    }
+
+    Value::Object(simplified)
+}
+
+/// Resolves a local JSON pointer reference such as `#/$defs/Item`
+fn resolve_reference<'a>(root: &'a Value, reference: &str) -> Option<&'a Value> {
+    let pointer = reference.strip_prefix('#')?;
+    root.pointer(pointer)
+}
+
+/// Merges the `allOf` variants into the schema. `properties` and `required` are combined, and
+/// `None` is returned when other keywords conflict, so that the `allOf` is kept.
+fn merge_all_of(schema: &Map<String, Value>, variants: &[Value]) -> Option<Map<String, Value>> {
+    let mut merged = schema.clone();
+    merged.remove("allOf");
+
+    for variant in variants.iter().filter_map(|v| v.as_object()) {
+        for (key, value) in variant {
+            match (key.as_str(), merged.get_mut(key), value) {
+                (_, None, _) => {
+                    merged.insert(key.clone(), value.clone());
+                }
+                (_, Some(existing), _) if existing == value => {}
+                (
+                    "properties",
+                    Some(Value::Object(properties)),
+                    Value::Object(variant_properties),
+                ) => {
+                    for (name, property) in variant_properties {
+                        match properties.get(name) {
+                            Some(existing) if existing != property => return None,
+                            Some(_) => {}
+                            None => {
+                                properties.insert(name.clone(), property.clone());
+                            }
+                        }
+                    }
+                }
+                ("required", Some(Value::Array(required)), Value::Array(variant_required)) => {
+                    for name in variant_required {
+                        if !required.contains(name) {
+                            required.push(name.clone());
+                        }
+                    }
+                }
+                // Descriptions don't constrain the value, the first one is kept
+                ("description" | "title", Some(_), _) => {}
+                _ => return None,
+            }
+        }
+    }
+
+    Some(merged)
+}
+
+/// Replaces `anyOf`/`oneOf`/`allOf` with an equivalent plain schema when it can be done safely
+fn flatten_unions(schema: &mut Map<String, Value>) {
+    if let Some(Value::Array(variants)) = schema.get("allOf")
+        && variants.iter().all(|v| v.is_object())
+        && let Some(merged) = merge_all_of(schema, variants)
+    {
+        *schema = merged;
+    }
+
+    for keyword in ["anyOf", "oneOf"] {
+        let Some(Value::Array(variants)) = schema.get(keyword).cloned() else {
+            continue;
+        };
+
+        if !variants.iter().all(|v| v.is_object()) {
+            continue;
+        }
+
+        let non_null: Vec<&Map<String, Value>> = variants
+            .iter()
+            .filter_map(|v| v.as_object())
+            .filter(|v| v.get("type") != Some(&Value::String("null".to_string())))
+            .collect();
+        if non_null.is_empty() {
+            continue;
+        }
+
+        let flattened =
+            if non_null.len() == 1 {
+                // Optional value: keep the only non-null variant
+                Some(non_null[0].clone())
+            } else if variants.iter().filter_map(|v| v.as_object()).all(|v| {
+                v.keys().all(|k| k == "type" || k == "description") && v.contains_key("type")
+            }) {
+                // Simple types: merge them into a type list
+                let mut types: Vec<Value> = Vec::new();
+                for variant in variants.iter().filter_map(|v| v.as_object()) {
+                    let variant_types = match variant.get("type") {
+                        Some(Value::Array(variant_types)) => variant_types.clone(),
+                        Some(variant_type) => vec![variant_type.clone()],
+                        None => vec![],
+                    };
+                    for variant_type in variant_types {
+                        if !types.contains(&variant_type) {
+                            types.push(variant_type);
+                        }
+                    }
+                }
+                let mut flattened = Map::new();
+                flattened.insert("type".to_string(), Value::Array(types));
+                Some(flattened)
+            } else if non_null.iter().all(|v| v.contains_key("const")) {
+                // Constants: merge them into an enum
+                let values: Vec<Value> = non_null
+                    .iter()
+                    .filter_map(|v| v.get("const").cloned())
+                    .collect();
+                let mut flattened = Map::new();
+                if let Some(variant_type) = non_null[0].get("type")
+                    && non_null.iter().all(|v| v.get("type") == Some(variant_type))
+                {
+                    flattened.insert("type".to_string(), variant_type.clone());
+                }
+                flattened.insert("enum".to_string(), Value::Array(values));
+                Some(flattened)
+            } else {
+                None
+            };
+
+        if let Some(flattened) = flattened {
+            schema.remove(keyword);
+            for (key, value) in flattened {
+                if key == "description" && schema.contains_key("description") {
+                    continue;
+                }
+                schema.insert(key, value);
+            }
+        }
+    }
+}
+
+fn reduce_large_enum(schema: &mut Map<String, Value>) {
+    let Some(Value::Array(values)) = schema.get("enum") else {
+        return;
+    };
+    if values.len() <= MAX_ENUM_VALUES {
+        return;
+    }
+
+    let examples = values
+        .iter()
+        .take(MAX_ENUM_VALUES)
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let note = format!(
+        "Allowed values include {} ({} more)",
+        examples,
+        values.len() - MAX_ENUM_VALUES
+    );
+
+    let description = match schema.get("description").and_then(|d| d.as_str()) {
+        Some(description) => format!("{} {}", description, note),
+        None => note,
+    };
+    schema.remove("enum");
+    schema.insert("description".to_string(), Value::String(description));
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn simplify(schema: Value) -> Value {
+        Value::Object(simplify_schema(schema.as_object().unwrap(), 0))
+    }
+
+    #[test]
+    fn inlines_references_and_stops_at_recursion() {
+        let simplified = simplify(json!({
+            "type": "object",
+            "properties": { "root": { "$ref": "#/$defs/Node" } },
+            "$defs": {
+                "Node": {
+                    "type": "object",
+                    "properties": {
+                        "value": { "type": "string" },
+                        "child": { "$ref": "#/$defs/Node" }
+                    }
+                }
+            }
+        }));
+
+        assert_eq!(
+            simplified,
+            json!({
+                "type": "object",
+                "properties": {
+                    "root": {
+                        "type": "object",
+                        "properties": {
+                            "value": { "type": "string" },
+                            "child": { "type": "object" }
+                        }
+                    }
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn merges_all_of_properties_and_required() {
+        let simplified = simplify(json!({
+            "allOf": [
+                {
+                    "type": "object",
+                    "properties": { "path": { "type": "string" } },
+                    "required": ["path"]
+                },
+                {
+                    "properties": { "recursive": { "type": "boolean" } },
+                    "required": ["recursive"]
+                }
+            ]
+        }));
+
+        assert_eq!(
+            simplified,
+            json!({
+                "type": "object",
+                "properties": {
+                    "path": { "type": "string" },
+                    "recursive": { "type": "boolean" }
+                },
+                "required": ["path", "recursive"]
+            })
+        );
+    }
+
+    #[test]
+    fn keeps_all_of_with_conflicting_keywords() {
+        let schema = json!({ "allOf": [{ "type": "string" }, { "type": "integer" }] });
+        assert_eq!(simplify(schema.clone()), schema);
+    }
+
+    #[test]
+    fn flattens_nullable_any_of() {
+        let simplified = simplify(json!({
+            "type": "object",
+            "properties": {
+                "limit": {
+                    "anyOf": [{ "type": "integer" }, { "type": "null" }],
+                    "description": "Maximum number of results"
+                }
+            }
+        }));
+
+        assert_eq!(
+            simplified["properties"]["limit"],
+            json!({ "type": "integer", "description": "Maximum number of results" })
+        );
+    }
+
+    #[test]
+    fn describes_large_enums() {
+        let values: Vec<String> = (0..MAX_ENUM_VALUES + 5)
+            .map(|i| format!("v{}", i))
+            .collect();
+        let simplified = simplify(json!({
+            "type": "string",
+            "description": "Region.",
+            "enum": values
+        }));
+
+        assert!(simplified.get("enum").is_none());
+        let description = simplified["description"].as_str().unwrap();
+        assert!(description.starts_with("Region. Allowed values include \"v0\", \"v1\""));
+        assert!(description.ends_with("\"v24\" (5 more)"));
+    }
+}
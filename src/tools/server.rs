@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
 
 use reqwest::{Client, header::HeaderMap};
 use rmcp::{
@@ -12,12 +12,14 @@ use rmcp::{
 };
 use tokio::process::Command;
 
-use crate::{AppResult, tools::tool::MCPTool};
+use crate::{
+    AppResult,
+    tools::{glob_match, tool::MCPTool},
+};
 
 #[derive(Debug, Clone)]
 pub enum MCPServerConfig {
     Stdio {
-        #[allow(dead_code)]
         name: String,
         command: String,
         args: Option<Vec<String>>,
@@ -25,14 +27,12 @@ pub enum MCPServerConfig {
         disabled: bool,
     },
     SSE {
-        #[allow(dead_code)]
         name: String,
         url: String,
         headers: Option<HeaderMap>,
         disabled: bool,
     },
     StreamableHttp {
-        #[allow(dead_code)]
         name: String,
         url: String,
         headers: Option<HeaderMap>,
@@ -40,18 +40,53 @@ pub enum MCPServerConfig {
     },
 }
 
+impl MCPServerConfig {
+    pub fn name(&self) -> &str {
+        match self {
+            MCPServerConfig::Stdio { name, .. }
+            | MCPServerConfig::SSE { name, .. }
+            | MCPServerConfig::StreamableHttp { name, .. } => name,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MCPServerOptions {
+    pub max_result_length: Option<usize>,
+    pub tool_result_limits: HashMap<String, usize>,
+    /// When set, only these tools are enabled at startup
+    pub enabled_tools: Option<Vec<String>>,
+    pub disabled_tools: Vec<String>,
+    /// Glob patterns of tools whose results can be cached even without a read-only annotation
+    pub cached_tools: Vec<String>,
+    /// Configuration file the server was loaded from, used to persist tool states
+    pub config_path: Option<PathBuf>,
+}
+
+impl MCPServerOptions {
+    pub fn is_tool_enabled(&self, name: &str) -> bool {
+        let allowed = match &self.enabled_tools {
+            Some(enabled_tools) => enabled_tools.iter().any(|t| t == name),
+            None => true,
+        };
+        allowed && !self.disabled_tools.iter().any(|t| t == name)
+    }
+}
+
 #[derive(Debug)]
 pub struct MCPServer {
     pub config: MCPServerConfig,
+    pub options: MCPServerOptions,
     pub client: Option<RunningService<RoleClient, ()>>,
     pub peer_info: Option<InitializeResult>,
     pub tools: Vec<MCPTool>,
 }
 
 impl MCPServer {
-    pub fn new(config: MCPServerConfig) -> Self {
+    pub fn new(config: MCPServerConfig, options: MCPServerOptions) -> Self {
         MCPServer {
             config,
+            options,
             client: None,
             peer_info: None,
             tools: Vec::new(),
@@ -149,9 +184,33 @@ impl MCPServer {
         self.peer_info = Some(peer_info);
         self.tools = tools;
 
+        for tool in &mut self.tools {
+            tool.enabled = self.options.is_tool_enabled(&tool.tool_info.name);
+            tool.saved_enabled = tool.enabled;
+        }
+
         Ok(())
     }
 
+    /// Returns the maximum result length configured for a tool of this server, if any
+    pub fn get_result_limit(&self, tool_name: &str) -> Option<usize> {
+        self.options
+            .tool_result_limits
+            .get(tool_name)
+            .copied()
+            .or(self.options.max_result_length)
+    }
+
+    /// Whether the results of a tool of this server can be reused for identical calls
+    pub fn is_tool_cacheable(&self, tool: &MCPTool) -> bool {
+        tool.is_read_only()
+            || self
+                .options
+                .cached_tools
+                .iter()
+                .any(|pattern| glob_match(pattern, &tool.tool_info.name))
+    }
+
     async fn fetch_info_from_client(
         client: &RunningService<RoleClient, ()>,
     ) -> AppResult<(InitializeResult, Vec<MCPTool>)> {
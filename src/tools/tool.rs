@@ -1,14 +1,21 @@
 use ollama_rs::generation::tools::{ToolFunctionInfo, ToolInfo};
 use rmcp::model::Tool;
 
+use crate::tools::schema::{shorten_description, simplify_schema};
+
 #[derive(Debug, Clone)]
 pub struct MCPTool {
     pub tool_info: Tool,
     pub enabled: bool,
+    /// Enabled state saved in the server configuration, which toolsets don't change
+    pub saved_enabled: bool,
 }
 
 pub trait ToToolInfo {
     fn to_tool_info(&self) -> ToolInfo;
+
+    /// Tool definition with a schema simplified for local models (see `simplify_schema`)
+    fn to_simplified_tool_info(&self, description_max_length: usize) -> ToolInfo;
 }
 
 impl ToToolInfo for Tool {
@@ -26,6 +33,20 @@ impl ToToolInfo for Tool {
             },
         }
     }
+
+    fn to_simplified_tool_info(&self, description_max_length: usize) -> ToolInfo {
+        ToolInfo {
+            tool_type: ollama_rs::generation::tools::ToolType::Function,
+            function: ToolFunctionInfo {
+                name: self.name.to_string(),
+                description: shorten_description(
+                    self.description.as_deref().unwrap_or_default(),
+                    description_max_length,
+                ),
+                parameters: simplify_schema(&self.input_schema, description_max_length).into(),
+            },
+        }
+    }
 }
 
 impl MCPTool {
@@ -33,6 +54,25 @@ impl MCPTool {
         MCPTool {
             tool_info,
             enabled: true,
+            saved_enabled: true,
         }
     }
+
+    /// Tool definition as sent to the model with the current schema settings
+    pub fn to_model_tool_info(&self, simplify: bool, description_max_length: usize) -> ToolInfo {
+        if simplify {
+            self.tool_info
+                .to_simplified_tool_info(description_max_length)
+        } else {
+            self.tool_info.to_tool_info()
+        }
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.tool_info
+            .annotations
+            .as_ref()
+            .and_then(|a| a.read_only_hint)
+            .unwrap_or(false)
+    }
 }
@@ -19,6 +19,59 @@ pub fn text_input(prompt: &str) -> String {
     input.trim().to_string()
 }
 
+/// Lets the user edit a text, in $VISUAL/$EDITOR when set, or inline otherwise.
+///
+/// Returns the initial text unchanged if editing fails or the inline input is left empty.
+pub fn edit_text(initial: &str, extension: &str) -> String {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .ok()
+        .filter(|e| !e.trim().is_empty());
+
+    match editor {
+        Some(editor) => {
+            // A new file with a random name, removed when dropped
+            let file = match tempfile::Builder::new()
+                .prefix(concat!(env!("CARGO_PKG_NAME"), "-"))
+                .suffix(&format!(".{}", extension))
+                .tempfile()
+            {
+                Ok(file) => file,
+                Err(_) => return initial.to_string(),
+            };
+            let file_path = file.path();
+            if std::fs::write(file_path, initial).is_err() {
+                return initial.to_string();
+            }
+
+            let mut parts = editor.split_whitespace();
+            let status = parts.next().and_then(|program| {
+                std::process::Command::new(program)
+                    .args(parts)
+                    .arg(file_path)
+                    .status()
+                    .ok()
+            });
+
+            match status {
+                Some(status) if status.success() => {
+                    std::fs::read_to_string(file_path).unwrap_or_else(|_| initial.to_string())
+                }
+                _ => initial.to_string(),
+            }
+        }
+        None => {
+            println!("{}", initial);
+            let edited = text_input("New value (empty to keep): ");
+            if edited.is_empty() {
+                initial.to_string()
+            } else {
+                edited
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct MenuChoice {
     pub name: String,
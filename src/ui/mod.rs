@@ -3,19 +3,26 @@ use std::{
     sync::{Arc, Mutex},
 };
 
-use ollama_rs::generation::{chat::ChatMessage, completion::request::GenerationRequest};
+use ollama_rs::generation::{
+    chat::ChatMessage, completion::request::GenerationRequest, parameters::KeepAlive,
+};
+use serde_json::Value;
 use tokio::io::{AsyncWriteExt, stdout};
 use tokio_stream::StreamExt;
 
 use crate::{
     chat::OllamaChat,
-    model::{render_model_info, select_model},
-    settings::SettingsManager,
-    tools::ToolManager,
+    model::{render_model_info, render_running_models, select_model},
+    ollama::{list_running_models, load_model},
+    settings::{SettingsManager, ThinkMode},
+    tools::{ToolManager, fallback::render_tools_prompt},
     ui::{
         input::MenuChoice,
-        tools::render_available_tools,
-        utils::{AnsiColor, colorize_text},
+        tools::{
+            prompt_tool_arguments, render_available_tools, render_tool_call_request,
+            render_tool_call_result, render_toolsets,
+        },
+        utils::{AnsiColor, colorize_text, page_text},
     },
 };
 
@@ -63,14 +70,28 @@ impl AppUI {
                 continue;
             }
 
-            let mut stream = self
+            let mut stream = match self
                 .ollama_chat
                 .chat(vec![ChatMessage::user(input.to_string())])
-                .await?;
+                .await
+            {
+                Ok(stream) => stream,
+                Err(err) => {
+                    eprintln!("{}", colorize_text(&err.to_string(), AnsiColor::BrightRed));
+                    continue;
+                }
+            };
+
+            let show_thinking = self
+                .settings_manager
+                .lock()
+                .unwrap()
+                .with_model_overrides()
+                .show_thinking;
 
             while let Some(res) = stream.recv().await {
                 if let Some(message) = res.message.thinking {
-                    if self.settings_manager.lock().unwrap().show_thinking {
+                    if show_thinking {
                         if !is_thinking {
                             stdout
                                 .write_all(
@@ -87,7 +108,7 @@ impl AppUI {
                     is_thinking = true;
                 } else {
                     if is_thinking {
-                        if self.settings_manager.lock().unwrap().show_thinking {
+                        if show_thinking {
                             stdout
                                 .write_all(
                                     colorize_text("</thinking>\n", AnsiColor::BrightBlack)
@@ -112,6 +133,118 @@ impl AppUI {
         exit(0);
     }
 
+    async fn call_tool(&mut self, args: &str) {
+        let (name, json_args) = match args.split_once(char::is_whitespace) {
+            Some((name, json_args)) => (name, Some(json_args.trim())),
+            None => (args, None),
+        };
+
+        if name.is_empty() {
+            println!("Usage: /tools call <name> [json-args]");
+            return;
+        }
+
+        let tool = match self.tool_manager.lock().await.get_tool(name) {
+            Some(tool) => tool,
+            None => {
+                println!("Tool '{}' not found.", name);
+                return;
+            }
+        };
+
+        let arguments = match json_args {
+            Some(json_args) => match serde_json::from_str::<Value>(json_args) {
+                Ok(Value::Object(arguments)) => arguments,
+                Ok(_) => {
+                    println!("Tool arguments must be a JSON object");
+                    return;
+                }
+                Err(err) => {
+                    println!("Invalid JSON arguments: {}", err);
+                    return;
+                }
+            },
+            None => prompt_tool_arguments(&tool.tool_info.input_schema).await,
+        };
+
+        println!(
+            "{}",
+            render_tool_call_request(name.to_string(), arguments.clone())
+        );
+
+        let call_result = self
+            .tool_manager
+            .lock()
+            .await
+            .call_tool(name.to_string(), arguments.clone())
+            .await;
+
+        match call_result {
+            Ok(result) => {
+                println!("{}", render_tool_call_result(&result.content));
+
+                let add_to_history = input::menu_selection(
+                    "Add to conversation history : ",
+                    vec![
+                        MenuChoice {
+                            name: "Yes".to_string(),
+                            shortcut: 'Y',
+                        },
+                        MenuChoice {
+                            name: "No".to_string(),
+                            shortcut: 'N',
+                        },
+                    ],
+                    false,
+                )
+                .await;
+
+                if add_to_history == 0 {
+                    self.ollama_chat
+                        .add_tool_call_to_history(name.to_string(), arguments, &result)
+                        .await;
+                }
+            }
+            Err(err) => eprintln!("Error calling tool {}: {}", name, err),
+        }
+    }
+
+    /// Shows the tools exactly as they are sent to the model with the current settings and
+    /// conversation: the definitions, or the system prompt text for models without native tool calling
+    async fn preview_tools(&self, name: &str) {
+        let (tools, prompt_tools) = match self.ollama_chat.model_tools(&[]).await {
+            Ok(model_tools) => model_tools,
+            Err(err) => {
+                eprintln!("{}", colorize_text(&err.to_string(), AnsiColor::BrightRed));
+                return;
+            }
+        };
+
+        let tools: Vec<_> = tools
+            .into_iter()
+            .filter(|tool| name.is_empty() || tool.function.name == name)
+            .collect();
+
+        if tools.is_empty() {
+            if name.is_empty() {
+                println!("No tools are sent to the model");
+            } else {
+                println!("No tool named '{}' is sent to the model", name);
+            }
+            return;
+        }
+
+        if prompt_tools {
+            page_text(&render_tools_prompt(&tools));
+            return;
+        }
+
+        match serde_json::to_string_pretty(&tools) {
+            Ok(json) => page_text(&json),
+            Err(err) => eprintln!("Error serializing tools: {}", err),
+        }
+    }
+
     async fn parse_command(&mut self, input: &str) -> bool {
         if input.starts_with('/') {
             let parts: Vec<&str> = input.split_whitespace().collect();
@@ -130,9 +263,29 @@ impl AppUI {
                     self.ollama_chat.clear();
                     println!("Context cleared !");
                 }
+                "/compact" => {
+                    let instructions = (!args.is_empty()).then_some(args.as_str());
+                    match self.ollama_chat.compact(instructions).await {
+                        Ok((tokens_before, tokens_after)) => println!(
+                            "Conversation compacted from about {} to {} tokens",
+                            tokens_before, tokens_after
+                        ),
+                        Err(err) => eprintln!(
+                            "{}",
+                            colorize_text(
+                                &format!("Error compacting the conversation: {}", err),
+                                AnsiColor::BrightRed
+                            )
+                        ),
+                    }
+                }
                 "/history" => {
                     dbg!(self.ollama_chat.get_history());
                 }
+                "/last-result" => match self.ollama_chat.get_last_tool_result() {
+                    Some(result) => page_text(&result),
+                    None => println!("No tool result available yet"),
+                },
                 "/tools" => match args.as_str() {
                     "show" => {
                         let tools = self.tool_manager.lock().await;
@@ -155,13 +308,88 @@ impl AppUI {
                             .collect::<Vec<_>>();
                         let selected = input::menu_toggle("Toggle Tools : ", choices).await;
 
+                        // Only the tools changed in the menu are saved, not the state set by a toolset
                         for choice in selected.iter() {
-                            tools.set_tool_status(&choice.0.name, choice.1).unwrap();
+                            if tools
+                                .get_tool(&choice.0.name)
+                                .is_some_and(|tool| tool.enabled != choice.1)
+                            {
+                                tools.set_tool_status(&choice.0.name, choice.1).unwrap();
+                            }
                         }
+                        tools.save_tool_status().unwrap_or_else(|err| {
+                            eprintln!("Error saving tool status: {}", err);
+                        });
                         println!("{}", render_available_tools(&tools.get_tools()));
                     }
+                    pin if pin.starts_with("pin ") || pin.starts_with("unpin ") => {
+                        let (action, pattern) = pin.split_once(' ').unwrap_or_default();
+                        let mut settings = self.settings_manager.lock().unwrap();
+                        if action == "pin" {
+                            if !settings.pinned_tools.iter().any(|p| p == pattern) {
+                                settings.pinned_tools.push(pattern.to_string());
+                            }
+                            println!("Pinned {}", pattern);
+                        } else {
+                            settings.pinned_tools.retain(|p| p != pattern);
+                            println!("Unpinned {}", pattern);
+                        }
+
+                        if settings.auto_save_config {
+                            settings
+                                .save_to_file(&settings.config_file_path)
+                                .unwrap_or_else(|err| {
+                                    eprintln!("Error saving settings: {}", err);
+                                });
+                        }
+                    }
+                    preview if preview == "preview" || preview.starts_with("preview ") => {
+                        self.preview_tools(preview["preview".len()..].trim()).await;
+                    }
+                    "cache clear" => {
+                        let cleared = self.tool_manager.lock().await.clear_result_cache();
+                        println!("Tool result cache cleared ({} results removed)", cleared);
+                    }
+                    call if call == "call" || call.starts_with("call ") => {
+                        let raw_args = input[command.len()..].trim_start()["call".len()..].trim();
+                        self.call_tool(raw_args).await;
+                    }
+                    _ => {
+                        println!(
+                            "Usage: /tools [show|toggle|call <name> [json-args]|preview [name]|pin <pattern>|unpin <pattern>|cache clear]"
+                        );
+                    }
+                },
+                "/toolset" => match args.split_once(' ') {
+                    Some(("use", name)) => {
+                        let patterns = self
+                            .settings_manager
+                            .lock()
+                            .unwrap()
+                            .toolsets
+                            .get(name.trim())
+                            .cloned();
+
+                        match patterns {
+                            Some(patterns) => {
+                                let mut tools = self.tool_manager.lock().await;
+                                let enabled_count = tools.apply_toolset(&patterns);
+                                println!(
+                                    "Toolset {} applied ({} tools enabled)",
+                                    name.trim(),
+                                    enabled_count
+                                );
+                                println!("{}", render_available_tools(&tools.get_tools()));
+                            }
+                            None => println!("Unknown toolset: {}", name.trim()),
+                        }
+                    }
+                    _ if args == "list" => {
+                        let settings = self.settings_manager.lock().unwrap();
+                        println!("{}", render_toolsets(&settings.toolsets));
+                    }
                     _ => {
-                        println!("Usage: /tools [show|toggle]");
+                        println!("Usage: /toolset [list|use <name>]");
                     }
                 },
                 "/settings" => match args.as_str() {
@@ -170,8 +398,14 @@ impl AppUI {
                         println!("{}", settings.render(true));
                     }
                     "edit" => {
-                        let mut settings = self.settings_manager.lock().unwrap();
-                        settings.render_edit_menu().await;
+                        {
+                            let mut settings = self.settings_manager.lock().unwrap();
+                            settings.render_edit_menu().await;
+                        }
+
+                        if let Err(err) = self.ollama_chat.reconnect() {
+                            eprintln!("Error connecting to Ollama: {}", err);
+                        }
                     }
                     "save" => {
                         let settings = self.settings_manager.lock().unwrap();
@@ -185,6 +419,35 @@ impl AppUI {
                         println!("Usage: /settings [show|edit]");
                     }
                 },
+                "/think" => {
+                    let mut settings = self.settings_manager.lock().unwrap();
+                    if args.is_empty() {
+                        println!("Thinking: {}", settings.with_model_overrides().think.name());
+                    } else if let Some(mode) = ThinkMode::from_name(&args) {
+                        let model_name = settings.model_name.clone();
+                        // A model override would take precedence over the global setting
+                        match settings.model_overrides.get_mut(&model_name) {
+                            Some(overrides) if overrides.contains_key("think") => {
+                                overrides.insert(
+                                    "think".to_string(),
+                                    Value::String(mode.name().to_string()),
+                                );
+                            }
+                            _ => settings.think = mode,
+                        }
+                        println!("Thinking set to {}", mode.name());
+
+                        if settings.auto_save_config {
+                            settings
+                                .save_to_file(&settings.config_file_path)
+                                .unwrap_or_else(|err| {
+                                    eprintln!("Error saving settings: {}", err);
+                                });
+                        }
+                    } else {
+                        println!("Usage: /think [off|on|low|medium|high]");
+                    }
+                }
                 "/model" => match args.as_str() {
                     "info" => {
                         println!(
@@ -212,6 +475,7 @@ impl AppUI {
                                 });
 
                         if !model.is_empty() {
+                            self.ollama_chat.invalidate_model_info(&model);
                             let mut settings = self.settings_manager.lock().unwrap();
                             settings.model_name = model;
                             if settings.auto_save_config {
@@ -239,27 +503,55 @@ impl AppUI {
                         }
                     }
                     "load" => {
-                        match self
-                            .ollama_chat
-                            .ollama
-                            .generate(GenerationRequest::new(
-                                self.settings_manager.lock().unwrap().model_name.clone(),
-                                "",
-                            ))
-                            .await
+                        let settings = self.settings_manager.lock().unwrap().with_model_overrides();
+                        match load_model(
+                            &self.ollama_chat.ollama,
+                            &self.ollama_chat.http_client,
+                            &settings.model_name,
+                            settings.keep_alive(),
+                        )
+                        .await
                         {
                             Ok(_) => println!("Model loaded successfully!"),
                             Err(err) => eprintln!("Error loading model: {}", err),
                         };
                     }
+                    "unload" => {
+                        let model_name = self.settings_manager.lock().unwrap().model_name.clone();
+                        let request = GenerationRequest::new(model_name, "")
+                            .keep_alive(KeepAlive::UnloadOnCompletion);
+                        match self.ollama_chat.ollama.generate(request).await {
+                            Ok(_) => println!("Model unloaded successfully!"),
+                            Err(err) => eprintln!("Error unloading model: {}", err),
+                        };
+                    }
+                    "ps" => {
+                        match list_running_models(
+                            &self.ollama_chat.ollama,
+                            &self.ollama_chat.http_client,
+                        )
+                        .await
+                        {
+                            Ok(models) => println!(
+                                "{}",
+                                RoundedBox::new(
+                                    &render_running_models(&models),
+                                    Some("Loaded Models"),
+                                    Some(AnsiColor::BrightBlue),
+                                    false
+                                )
+                                .render()
+                            ),
+                            Err(err) => eprintln!("Error listing loaded models: {}", err),
+                        }
+                    }
                     "pull" => {
+                        let model_name = self.settings_manager.lock().unwrap().model_name.clone();
+                        self.ollama_chat.invalidate_model_info(&model_name);
                         match self
                             .ollama_chat
                             .ollama
-                            .pull_model_stream(
-                                self.settings_manager.lock().unwrap().model_name.clone(),
-                                false,
-                            )
+                            .pull_model_stream(model_name, false)
                             .await
                         {
                             Ok(mut stream) => {
@@ -288,23 +580,46 @@ impl AppUI {
                         }
                     }
                     _ => {
-                        println!("Usage: /model [info|select|load|pull]");
+                        println!("Usage: /model [info|select|load|unload|ps|pull]");
                     }
                 },
                 "/help" => {
                     let help = vec![
                         ("/quit", "Exit the application"),
                         ("/clear", "Clear the chat context"),
+                        (
+                            "/compact [instructions]",
+                            "Replace the chat context with a summary",
+                        ),
                         ("/history", "Show chat history"),
                         ("/tools [show|toggle]", "List or Toggle available tools"),
+                        (
+                            "/tools call <name> [json-args]",
+                            "Call a tool directly, asking for its arguments if omitted",
+                        ),
+                        (
+                            "/tools preview [name]",
+                            "Show the tool definitions as sent to the model",
+                        ),
+                        (
+                            "/tools [pin|unpin] <pattern>",
+                            "Always send matching tools when tool retrieval is enabled",
+                        ),
+                        ("/tools cache clear", "Clear the tool result cache"),
+                        ("/toolset [list|use <name>]", "List or Apply toolsets"),
+                        ("/last-result", "Show the last tool result in full"),
                         (
                             "/settings [show|edit|save]",
                             "Show, Edit or Save current settings",
                         ),
                         (
-                            "/model [info|select|load|pull]",
+                            "/model [info|select|load|unload|ps|pull]",
                             "Manage model used by Ollama",
                         ),
+                        (
+                            "/think [off|on|low|medium|high]",
+                            "Show or Change how much the model thinks",
+                        ),
                         ("/help", "Show this help message"),
                     ];
 
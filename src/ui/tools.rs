@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use rmcp::model::{Annotated, RawContent};
 use serde_json::{Map, Value};
 
@@ -5,6 +7,7 @@ use crate::{
     tools::tool::MCPTool,
     ui::{
         RoundedBox,
+        input::{MenuChoice, menu_selection, text_input},
         utils::{AnsiColor, colorize_text},
     },
 };
@@ -50,6 +53,33 @@ pub fn render_available_tools(tools: &[MCPTool]) -> String {
     .render()
 }
 
+pub fn render_toolsets(toolsets: &HashMap<String, Vec<String>>) -> String {
+    let mut output = String::new();
+
+    let mut names: Vec<&String> = toolsets.keys().collect();
+    names.sort();
+
+    for name in names {
+        output.push_str(&format!(
+            "{} : {}\n",
+            colorize_text(name, AnsiColor::BrightCyan),
+            toolsets[name].join(", ")
+        ));
+    }
+
+    if output.is_empty() {
+        output = "No toolsets defined".to_string();
+    }
+
+    RoundedBox::new(
+        &output,
+        Some("Toolsets"),
+        Some(AnsiColor::BrightBlue),
+        false,
+    )
+    .render()
+}
+
 pub fn render_tool_call_request(name: String, args: Map<String, Value>) -> String {
     RoundedBox::new(
         &format!(
@@ -64,6 +94,31 @@ pub fn render_tool_call_request(name: String, args: Map<String, Value>) -> Strin
     .render()
 }
 
+pub fn render_tool_call_error(name: &str, error: &str) -> String {
+    RoundedBox::new(
+        &format!("Name: {}\nError: \n{}", name, error),
+        Some("Tool Call Error"),
+        Some(AnsiColor::BrightRed),
+        false,
+    )
+    .render()
+}
+
+/// Formats every content item of a tool result as plain text, pretty-printing JSON payloads
+pub fn format_tool_result_content(result: &[Annotated<RawContent>]) -> String {
+    result
+        .iter()
+        .map(|content| match content.as_text() {
+            Some(text) => serde_json::from_str::<Value>(&text.text)
+                .ok()
+                .and_then(|v| serde_json::to_string_pretty(&v).ok())
+                .unwrap_or_else(|| text.text.clone()),
+            None => serde_json::to_string_pretty(content).unwrap_or_default(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
 pub fn render_tool_call_result(result: &Vec<Annotated<RawContent>>) -> String {
     let result = match result.get(0) {
         Some(first_result) => serde_json::to_value(first_result)
@@ -83,3 +138,107 @@ pub fn render_tool_call_result(result: &Vec<Annotated<RawContent>>) -> String {
     )
     .render()
 }
+
+/// Asks the user for every property of a tool input schema, using an input suited to each property type
+pub async fn prompt_tool_arguments(schema: &Map<String, Value>) -> Map<String, Value> {
+    let mut arguments = Map::new();
+
+    let properties = match schema.get("properties").and_then(|p| p.as_object()) {
+        Some(properties) => properties,
+        None => return arguments,
+    };
+
+    let required: Vec<&str> = schema
+        .get("required")
+        .and_then(|r| r.as_array())
+        .map(|r| r.iter().filter_map(|v| v.as_str()).collect())
+        .unwrap_or_default();
+
+    for (name, property) in properties {
+        let is_required = required.contains(&name.as_str());
+        let property_type = property
+            .get("type")
+            .and_then(|t| t.as_str())
+            .unwrap_or("string");
+
+        println!(
+            "{} ({}{})",
+            colorize_text(name, AnsiColor::BrightCyan),
+            property_type,
+            if is_required { ", required" } else { "" }
+        );
+        if let Some(description) = property.get("description").and_then(|d| d.as_str()) {
+            println!("{}", colorize_text(description, AnsiColor::BrightBlack));
+        }
+
+        if let Some(value) = prompt_property_value(name, property, property_type, is_required).await
+        {
+            arguments.insert(name.clone(), value);
+        }
+    }
+
+    arguments
+}
+
+async fn prompt_property_value(
+    name: &str,
+    property: &Value,
+    property_type: &str,
+    is_required: bool,
+) -> Option<Value> {
+    let enum_values = property.get("enum").and_then(|e| e.as_array());
+
+    if property_type == "boolean" || enum_values.is_some() {
+        let mut values = match enum_values {
+            Some(values) => values.clone(),
+            None => vec![Value::Bool(true), Value::Bool(false)],
+        };
+        let mut choices: Vec<MenuChoice> = values
+            .iter()
+            .map(|v| MenuChoice {
+                name: v
+                    .as_str()
+                    .map(String::from)
+                    .unwrap_or_else(|| v.to_string()),
+                shortcut: '#',
+            })
+            .collect();
+
+        if !is_required {
+            choices.push(MenuChoice {
+                name: "Skip".to_string(),
+                shortcut: '#',
+            });
+            values.push(Value::Null);
+        }
+
+        return match values.swap_remove(menu_selection("", choices, true).await as usize) {
+            Value::Null => None,
+            value => Some(value),
+        };
+    }
+
+    loop {
+        let input = text_input(&format!("{}: ", name));
+
+        if input.is_empty() {
+            if is_required {
+                println!("{} is required", name);
+                continue;
+            }
+            return None;
+        }
+
+        let value = match property_type {
+            "integer" => input.parse::<i64>().ok().map(Value::from),
+            "number" => input.parse::<f64>().ok().map(Value::from),
+            "array" | "object" => serde_json::from_str::<Value>(&input).ok(),
+            _ => Some(Value::String(input)),
+        };
+
+        match value {
+            Some(value) => return Some(value),
+            None => println!("Invalid value for a property of type {}", property_type),
+        }
+    }
+}
@@ -1,5 +1,9 @@
 use regex::Regex;
-use std::cmp;
+use std::{
+    cmp,
+    io::Write,
+    process::{Command, Stdio},
+};
 use terminal_size::{Width, terminal_size};
 
 pub struct RoundedBox {
@@ -158,6 +162,30 @@ impl RoundedBox {
     }
 }
 
+/// Displays text through the user's pager ($PAGER, defaults to `less -R`), or prints it if none can be spawned
+pub fn page_text(text: &str) {
+    let pager = std::env::var("PAGER").unwrap_or_else(|_| "less -R".to_string());
+    let mut parts = pager.split_whitespace();
+
+    let child = parts.next().and_then(|program| {
+        Command::new(program)
+            .args(parts)
+            .stdin(Stdio::piped())
+            .spawn()
+            .ok()
+    });
+
+    match child {
+        Some(mut child) => {
+            if let Some(mut stdin) = child.stdin.take() {
+                let _ = stdin.write_all(text.as_bytes());
+            }
+            let _ = child.wait();
+        }
+        None => println!("{}", text),
+    }
+}
+
 pub fn colorize_text(text: &str, color: AnsiColor) -> String {
     format!("\x1b[{}m{}\x1b[0m", color.to_ansi_code(), text)
 }